@@ -0,0 +1,275 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+use std::io::Result;
+
+use crate::components::Connectivity;
+use crate::point::Point;
+use crate::save::{Persist, SaveReader, SaveWriter};
+
+// Guesses the remaining cost, has to never guess too high
+// (assuming the cheapest step costs 1) or paths stop being shortest.
+// Any fn will do, manhattan and chebyshev fit the two connectivities
+pub type Heuristic = fn((u16, u16), (u16, u16)) -> u32;
+
+fn distances(from: (u16, u16), to: (u16, u16)) -> (u32, u32) {
+    let dx = (from.0 as i32 - to.0 as i32).unsigned_abs();
+    let dy = (from.1 as i32 - to.1 as i32).unsigned_abs();
+
+    (dx, dy)
+}
+
+// for four way movement
+pub fn manhattan(from: (u16, u16), to: (u16, u16)) -> u32 {
+    let (dx, dy) = distances(from, to);
+    dx + dy
+}
+
+// for eight way movement, diagonals cost the same
+pub fn chebyshev(from: (u16, u16), to: (u16, u16)) -> u32 {
+    let (dx, dy) = distances(from, to);
+    dx.max(dy)
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PathOptions {
+    pub connectivity: Connectivity,
+    pub heuristic: Heuristic,
+    // what a step costs is the tile cost times one of these
+    pub step_cost: u32,
+    pub diagonal_cost: u32,
+    pub max_nodes: usize, // give up after expanding this many
+}
+
+impl PathOptions {
+    // picks the heuristic that fits the connectivity
+    pub fn new(connectivity: Connectivity) -> PathOptions {
+        let heuristic: Heuristic = match connectivity {
+            Connectivity::Four => manhattan,
+            Connectivity::Eight => chebyshev,
+        };
+
+        PathOptions {
+            connectivity,
+            heuristic,
+            step_cost: 1,
+            diagonal_cost: 1,
+            max_nodes: usize::MAX,
+        }
+    }
+}
+
+// Steps to walk, not including the start
+#[derive(Clone, Debug, PartialEq)]
+pub struct Path {
+    pub steps: VecDeque<(u16, u16)>,
+    pub cost: u32,
+}
+
+impl Path {
+    pub fn peek(&self) -> Option<(u16, u16)> {
+        self.steps.front().copied()
+    }
+
+    pub fn next_step(&mut self) -> Option<(u16, u16)> {
+        self.steps.pop_front()
+    }
+
+    pub fn goal(&self) -> Option<(u16, u16)> {
+        self.steps.back().copied()
+    }
+}
+
+// (estimated total, cost so far, position), lowest first
+type OpenSet = BinaryHeap<Reverse<(u32, u32, (u16, u16))>>;
+
+// cost gives what it takes to step onto a tile, None if you can't
+pub fn find_path<F>(start: (u16, u16), goal: (u16, u16), dimensions: (usize, usize), cost: F, options: &PathOptions) -> Option<Path>
+where
+    F: Fn((u16, u16)) -> Option<u32>,
+{
+    let (width, height) = dimensions;
+    let in_bounds = |p: (u16, u16)| (p.0 as usize) < width && (p.1 as usize) < height;
+
+    if !in_bounds(start) || !in_bounds(goal) {
+        return None;
+    }
+    if start == goal {
+        return Some(Path { steps: VecDeque::new(), cost: 0 });
+    }
+
+    let index = |p: (u16, u16)| p.0 as usize * height + p.1 as usize;
+
+    let mut g_score: Vec<u32> = vec![u32::MAX; width * height];
+    let mut came_from: Vec<Option<(u16, u16)>> = vec![None; width * height];
+    let mut closed: Vec<bool> = vec![false; width * height];
+
+    let mut open: OpenSet = BinaryHeap::new();
+
+    g_score[index(start)] = 0;
+    open.push(Reverse(((options.heuristic)(start, goal), 0, start)));
+
+    let mut expanded = 0;
+
+    while let Some(Reverse((_, g, current))) = open.pop() {
+        if current == goal {
+            return Some(build_path(&came_from, start, goal, g, index));
+        }
+        if closed[index(current)] {
+            continue; // already found a cheaper way here
+        }
+        closed[index(current)] = true;
+
+        expanded += 1;
+        if expanded > options.max_nodes {
+            return None;
+        }
+
+        for point in Point::from(current).neighbours(options.connectivity, dimensions) {
+            let neighbour: (u16, u16) = point.into();
+            if closed[index(neighbour)] {
+                continue;
+            }
+
+            let step_cost = match cost(neighbour) {
+                Some(c) => c,
+                None => continue,
+            };
+            let diagonal = neighbour.0 != current.0 && neighbour.1 != current.1;
+            let step_cost = match diagonal {
+                true => step_cost.saturating_mul(options.diagonal_cost),
+                false => step_cost.saturating_mul(options.step_cost),
+            };
+
+            let new_g = g.saturating_add(step_cost);
+            if new_g < g_score[index(neighbour)] {
+                g_score[index(neighbour)] = new_g;
+                came_from[index(neighbour)] = Some(current);
+
+                let f = new_g.saturating_add((options.heuristic)(neighbour, goal));
+                open.push(Reverse((f, new_g, neighbour)));
+            }
+        }
+    }
+
+    None
+}
+
+fn build_path<I>(came_from: &[Option<(u16, u16)>], start: (u16, u16), goal: (u16, u16), cost: u32, index: I) -> Path
+where
+    I: Fn((u16, u16)) -> usize,
+{
+    let mut steps: VecDeque<(u16, u16)> = VecDeque::new();
+    let mut current = goal;
+
+    while current != start {
+        steps.push_front(current);
+        current = match came_from[index(current)] {
+            Some(previous) => previous,
+            None => break,
+        };
+    }
+
+    Path {
+        steps,
+        cost,
+    }
+}
+
+impl Persist for Path {
+    fn save(&self, out: &mut SaveWriter) {
+        out.write(&self.steps);
+        out.write(&self.cost);
+    }
+
+    fn load(input: &mut SaveReader) -> Result<Self> {
+        Ok(Path {
+            steps: input.read()?,
+            cost: input.read()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 7x7, a wall down x = 3 with a gap at the bottom
+    fn walled(position: (u16, u16)) -> Option<u32> {
+        match position {
+            (3, y) if y < 6 => None,
+            _ => Some(1),
+        }
+    }
+
+    fn open(_: (u16, u16)) -> Option<u32> {
+        Some(1)
+    }
+
+    #[test]
+    fn path_goes_round_the_wall() {
+        let options = PathOptions::new(Connectivity::Four);
+        let path = find_path((1, 1), (5, 1), (7, 7), walled, &options).unwrap();
+
+        assert_eq!(path.cost, 14);
+        assert_eq!(path.steps.len(), 14);
+        assert_eq!(path.goal(), Some((5, 1)));
+        assert!(path.steps.contains(&(3, 6)));
+
+        // every step is next to the last one and off the wall
+        let mut previous = (1, 1);
+        for step in path.steps.iter() {
+            assert!(walled(*step).is_some());
+            assert_eq!(manhattan(previous, *step), 1);
+            previous = *step;
+        }
+    }
+
+    #[test]
+    fn unreachable_goal_has_no_path() {
+        let closed = |position: (u16, u16)| match position {
+            (3, _) => None,
+            _ => Some(1),
+        };
+        let options = PathOptions::new(Connectivity::Eight);
+
+        assert_eq!(find_path((1, 1), (5, 1), (7, 7), closed, &options), None);
+        // onto a wall, or off the map
+        assert_eq!(find_path((1, 1), (3, 1), (7, 7), walled, &options), None);
+        assert_eq!(find_path((1, 1), (9, 1), (7, 7), open, &options), None);
+    }
+
+    #[test]
+    fn giving_up_after_max_nodes() {
+        let mut options = PathOptions::new(Connectivity::Four);
+        options.max_nodes = 5;
+
+        assert_eq!(find_path((1, 1), (5, 1), (7, 7), walled, &options), None);
+    }
+
+    #[test]
+    fn diagonal_steps_cost_what_they_are_set_to() {
+        let four = PathOptions::new(Connectivity::Four);
+        let eight = PathOptions::new(Connectivity::Eight);
+        let dear_diagonals = PathOptions { diagonal_cost: 3, ..eight };
+        let dear_steps = PathOptions { step_cost: 2, ..four };
+
+        let cost = |options: &PathOptions| find_path((0, 0), (3, 3), (5, 5), open, options).unwrap().cost;
+
+        assert_eq!(cost(&four), 6);
+        assert_eq!(cost(&eight), 3);
+        // two straight steps are cheaper than one diagonal now
+        assert_eq!(cost(&dear_diagonals), 6);
+        assert_eq!(cost(&dear_steps), 12);
+    }
+
+    // any fn will do, no guess at all still finds the shortest
+    #[test]
+    fn heuristic_can_be_swapped() {
+        let options = PathOptions { heuristic: |_, _| 0, ..PathOptions::new(Connectivity::Four) };
+        let path = find_path((1, 1), (5, 1), (7, 7), walled, &options).unwrap();
+
+        assert_eq!(path.cost, 14);
+        assert_eq!(chebyshev((1, 1), (5, 4)), 4);
+        assert_eq!(manhattan((1, 1), (5, 4)), 7);
+    }
+}
@@ -0,0 +1,248 @@
+use std::io::{stdout, Write};
+
+use crossterm::{
+    execute, queue,
+    style::{Color, Print, PrintStyledContent, ResetColor, SetBackgroundColor, SetForegroundColor,
+    StyledContent},
+    Result,
+    terminal::{EnterAlternateScreen, LeaveAlternateScreen, SetSize,
+    disable_raw_mode, enable_raw_mode, Clear, ClearType::{All}},
+    cursor::{MoveTo, MoveToColumn, MoveToRow, Hide, Show},
+};
+
+use crate::renderer::Dimemsion;
+
+// Where the renderer sends its finished cells.
+// A run is a row of adjacent cells starting at position,
+// nothing has to reach the screen until flush
+pub trait Backend {
+    fn initialize(&mut self, view_port: Dimemsion) -> Result<()>;
+    fn resize(&mut self, size: Dimemsion) -> Result<()>; // eg. to make room for the panel
+    fn reset(&mut self) -> Result<()>;
+    fn draw_run(&mut self, position: (u16, u16), run: &[StyledContent<char>]) -> Result<()>;
+    fn flush(&mut self) -> Result<usize>; // bytes written this frame
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn std::any::Any;
+}
+
+// Turns runs into escape codes, only moving the cursor
+// and changing colour when it actually has to
+pub struct FrameEncoder {
+    pub bytes: Vec<u8>,
+    cursor: Option<(u16, u16)>,
+    foreground: Color,
+    background: Color,
+}
+
+impl FrameEncoder {
+    pub fn new() -> FrameEncoder {
+        FrameEncoder {
+            bytes: Vec::new(),
+            cursor: None,
+            foreground: Color::Reset,
+            background: Color::Reset,
+        }
+    }
+
+    pub fn encode_run(&mut self, position: (u16, u16), run: &[StyledContent<char>]) -> Result<()> {
+        if self.cursor != Some(position) {
+            queue!(self.bytes, MoveTo(position.0, position.1))?;
+        }
+
+        for character in run {
+            let style = character.style();
+
+            if !style.attributes.is_empty() {
+                // rare, let crossterm handle it, it resets colours after
+                queue!(self.bytes, PrintStyledContent(*character))?;
+                self.foreground = Color::Reset;
+                self.background = Color::Reset;
+                continue;
+            }
+
+            let foreground = style.foreground_color.unwrap_or(Color::Reset);
+            let background = style.background_color.unwrap_or(Color::Reset);
+
+            if foreground != self.foreground {
+                queue!(self.bytes, SetForegroundColor(foreground))?;
+                self.foreground = foreground;
+            }
+            if background != self.background {
+                queue!(self.bytes, SetBackgroundColor(background))?;
+                self.background = background;
+            }
+            queue!(self.bytes, Print(*character.content()))?;
+        }
+
+        self.cursor = Some((position.0 + run.len() as u16, position.1));
+        Ok(())
+    }
+
+    // hand over the frame, keeps cursor and colour state for the next one
+    pub fn take(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.bytes)
+    }
+
+    // terminal state is unknown, eg. after a clear
+    pub fn invalidate(&mut self) {
+        self.cursor = None;
+        self.foreground = Color::Reset;
+        self.background = Color::Reset;
+    }
+}
+
+// Real terminal
+pub struct CrosstermBackend {
+    encoder: FrameEncoder,
+}
+
+impl CrosstermBackend {
+    pub fn new() -> CrosstermBackend {
+        CrosstermBackend {
+            encoder: FrameEncoder::new(),
+        }
+    }
+}
+
+impl Backend for CrosstermBackend {
+    fn initialize(&mut self, view_port: Dimemsion) -> Result<()> {
+        enable_raw_mode()?;
+        execute!(
+            stdout(), EnterAlternateScreen, Hide,
+            SetBackgroundColor(Color::Black),
+            SetForegroundColor(Color::Black),
+        )?;
+
+        execute!(
+            stdout(),
+            SetSize(view_port.0, view_port.1), // columns, rows
+            Clear(All),
+            MoveToColumn(0),
+            MoveToRow(0),
+            ResetColor,
+        )?;
+        self.encoder.invalidate();
+
+        Ok(())
+    }
+
+    fn resize(&mut self, size: Dimemsion) -> Result<()> {
+        execute!(stdout(), SetSize(size.0, size.1), Clear(All))?;
+        self.encoder.invalidate();
+        Ok(())
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        disable_raw_mode()?;
+        execute!(stdout(), ResetColor, LeaveAlternateScreen, Show)?;
+        Ok(())
+    }
+
+    fn draw_run(&mut self, position: (u16, u16), run: &[StyledContent<char>]) -> Result<()> {
+        self.encoder.encode_run(position, run)
+    }
+
+    fn flush(&mut self) -> Result<usize> {
+        let frame = self.encoder.take();
+
+        // one write per frame
+        let mut out = stdout();
+        out.write_all(&frame)?;
+        out.flush()?;
+
+        Ok(frame.len())
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+// No terminal at all, just remembers what would be on screen.
+// Used for tests and CI
+pub struct HeadlessBackend {
+    pub grid: Vec<Vec<Option<StyledContent<char>>>>, // [column][row]
+    pub frames: usize,
+    encoder: FrameEncoder, // so byte counts match a real terminal
+}
+
+impl HeadlessBackend {
+    pub fn new() -> HeadlessBackend {
+        HeadlessBackend {
+            grid: Vec::new(),
+            frames: 0,
+            encoder: FrameEncoder::new(),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn cell(&self, position: (u16, u16)) -> Option<StyledContent<char>> {
+        match self.grid.get(position.0 as usize) {
+            Some(column) => match column.get(position.1 as usize) {
+                Some(cell) => *cell,
+                None => None,
+            },
+            None => None,
+        }
+    }
+
+    // the characters of one row, blank for cells never drawn
+    #[cfg(test)]
+    pub fn row_text(&self, row: u16) -> String {
+        let mut line = String::new();
+
+        for x in 0..self.grid.len() {
+            match self.cell((x as u16, row)) {
+                Some(character) => line.push(*character.content()),
+                None => line.push(' '),
+            }
+        }
+
+        line
+    }
+}
+
+impl Backend for HeadlessBackend {
+    fn initialize(&mut self, view_port: Dimemsion) -> Result<()> {
+        // renderer draws one past the view port
+        self.grid = vec![vec![None; view_port.1 as usize + 1]; view_port.0 as usize + 1];
+        self.frames = 0;
+        self.encoder.invalidate();
+        Ok(())
+    }
+
+    fn resize(&mut self, size: Dimemsion) -> Result<()> {
+        self.grid = vec![vec![None; size.1 as usize + 1]; size.0 as usize + 1];
+        self.encoder.invalidate();
+        Ok(())
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn draw_run(&mut self, position: (u16, u16), run: &[StyledContent<char>]) -> Result<()> {
+        let y = position.1 as usize;
+
+        for (i, character) in run.iter().enumerate() {
+            let x = position.0 as usize + i;
+
+            if x < self.grid.len() && y < self.grid[x].len() {
+                self.grid[x][y] = Some(*character);
+            }
+        }
+
+        self.encoder.encode_run(position, run)
+    }
+
+    fn flush(&mut self) -> Result<usize> {
+        self.frames += 1;
+        Ok(self.encoder.take().len())
+    }
+
+    #[cfg(test)]
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
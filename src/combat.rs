@@ -0,0 +1,35 @@
+use crate::components::{AttackComponent, DefenseComponent};
+use crate::world::Entity;
+
+// Every hit that landed this tick, kept so other systems
+// (messages, effects) can see what happened
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DamageEvent {
+    pub attacker: Entity,
+    pub target: Entity,
+    pub amount: u32,
+    pub killed: bool,
+}
+
+// armour takes straight off the attack, a hit can do nothing
+pub fn resolve_attack(attack: &AttackComponent, defense: Option<&DefenseComponent>) -> u32 {
+    match defense {
+        Some(defense) => attack.power.saturating_sub(defense.armor),
+        None => attack.power,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn armour_takes_off_the_attack() {
+        let attack = AttackComponent::new(5);
+
+        assert_eq!(resolve_attack(&attack, None), 5);
+        assert_eq!(resolve_attack(&attack, Some(&DefenseComponent::new(2))), 3);
+        // never below nothing
+        assert_eq!(resolve_attack(&attack, Some(&DefenseComponent::new(9))), 0);
+    }
+}
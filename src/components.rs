@@ -1,27 +1,116 @@
 use crate::renderer::Buffer;
+use crate::a_star::Path;
+use crate::point::Point;
+use crate::save::{corrupt, Persist, SaveReader, SaveWriter};
+use std::io::Result;
 
-#[derive(Clone, Debug)]
-pub enum ComponentList {
-    Render(RenderComponent),
-    Collision(CollisionComponent),
-    Energy(EnergyComponent),
-    Movement(MovementComponent),
-}
-
-#[derive(Clone, Debug)]
+// How often an entity gets a turn, see scheduler::NORMAL_SPEED
+#[derive(Clone, Debug, PartialEq)]
 pub struct EnergyComponent {
-    pub energy: u8,
+    pub speed: u32,
 }
 
 impl EnergyComponent {
-    pub fn new(energy: u8) -> EnergyComponent {
+    pub fn new(speed: u32) -> EnergyComponent {
         EnergyComponent { 
-            energy,
+            speed,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HealthComponent {
+    pub current: u32,
+    pub max: u32,
+}
+
+impl HealthComponent {
+    pub fn new(max: u32) -> HealthComponent {
+        HealthComponent {
+            current: max,
+            max,
+        }
+    }
+
+    // true if that killed it
+    pub fn take_damage(&mut self, amount: u32) -> bool {
+        self.current = self.current.saturating_sub(amount);
+        self.is_dead()
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current == 0
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AttackComponent {
+    pub power: u32,
+}
+
+impl AttackComponent {
+    pub fn new(power: u32) -> AttackComponent {
+        AttackComponent { power }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DefenseComponent {
+    pub armor: u32,
+}
+
+impl DefenseComponent {
+    pub fn new(armor: u32) -> DefenseComponent {
+        DefenseComponent { armor }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Faction {
+    Player,
+    Monster,
+}
+
+// Bumping into a different faction is an attack
+#[derive(Clone, Debug, PartialEq)]
+pub struct FactionComponent {
+    pub faction: Faction,
+}
+
+impl FactionComponent {
+    pub fn new(faction: Faction) -> FactionComponent {
+        FactionComponent { faction }
+    }
+
+    pub fn is_hostile(&self, other: &FactionComponent) -> bool {
+        self.faction != other.faction
+    }
+}
+
+// Opens when bumped, stops blocking and swaps its character.
+// Interacting next to an open one closes it again
+#[derive(Clone, Debug, PartialEq)]
+pub struct DoorComponent {
+    pub open: bool,
+    pub closed_character: u8,
+    pub open_character: u8,
+}
+
+impl DoorComponent {
+    pub fn new(closed_character: u8, open_character: u8) -> DoorComponent {
+        DoorComponent {
+            open: false,
+            closed_character,
+            open_character,
         }
     }
 }
 
-#[derive(Clone, Debug)]
+// Bumping it shoves it one cell the same way, if there is room
+#[derive(Clone, Debug, PartialEq)]
+pub struct PushableComponent;
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct CollisionComponent {
     pub position: (u16, u16),
     //pub bottom_right: (u16, u16),
@@ -40,7 +129,7 @@ impl CollisionComponent {
 
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct RenderComponent {
     pub character: u8,
     pub backgroud: u8,
@@ -79,44 +168,321 @@ impl RenderComponent {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct MovementComponent {
     pub desired_position: (u16, u16),
+    pub path: Option<Path>, // followed a step per turn
 }
 
-#[derive(Clone, Debug, Copy)]
+#[derive(Clone, Debug, Copy, PartialEq, Eq)]
 pub enum Direction {
     Up,
     Down,
     Left,
     Right,
+    // only used with Connectivity::Eight
+    UpLeft,
+    UpRight,
+    DownLeft,
+    DownRight,
+}
+
+impl Direction {
+    // (x, y) step, up is towards 0
+    pub fn offset(&self) -> (i32, i32) {
+        match self {
+            Direction::Up => (0, -1),
+            Direction::Down => (0, 1),
+            Direction::Left => (-1, 0),
+            Direction::Right => (1, 0),
+            Direction::UpLeft => (-1, -1),
+            Direction::UpRight => (1, -1),
+            Direction::DownLeft => (-1, 1),
+            Direction::DownRight => (1, 1),
+        }
+    }
+
+    pub fn from_offset(offset: (i32, i32)) -> Option<Direction> {
+        match offset {
+            (0, -1) => Some(Direction::Up),
+            (0, 1) => Some(Direction::Down),
+            (-1, 0) => Some(Direction::Left),
+            (1, 0) => Some(Direction::Right),
+            (-1, -1) => Some(Direction::UpLeft),
+            (1, -1) => Some(Direction::UpRight),
+            (-1, 1) => Some(Direction::DownLeft),
+            (1, 1) => Some(Direction::DownRight),
+            _ => None,
+        }
+    }
+
+    pub fn is_diagonal(&self) -> bool {
+        let offset = self.offset();
+        offset.0 != 0 && offset.1 != 0
+    }
+
+    // None when it would step off the top or left edge
+    pub fn step(&self, position: (u16, u16)) -> Option<(u16, u16)> {
+        Point::from(position).step(*self).map(|point| point.into())
+    }
+}
+
+// Which neighbours count as next to a cell
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Connectivity {
+    Four,
+    Eight,
+}
+
+impl Connectivity {
+    pub fn offsets(&self) -> &'static [(i32, i32)] {
+        match self {
+            Connectivity::Four => &[(-1, 0), (0, -1), (0, 1), (1, 0)],
+            Connectivity::Eight => &[
+                (-1, 0), (0, -1), (0, 1), (1, 0),
+                (-1, -1), (1, -1), (-1, 1), (1, 1),
+            ],
+        }
+    }
+
+    // same order as offsets
+    pub fn directions(&self) -> &'static [Direction] {
+        match self {
+            Connectivity::Four => &[Direction::Left, Direction::Up, Direction::Down, Direction::Right],
+            Connectivity::Eight => &[
+                Direction::Left, Direction::Up, Direction::Down, Direction::Right,
+                Direction::UpLeft, Direction::UpRight, Direction::DownLeft, Direction::DownRight,
+            ],
+        }
+    }
+
+    pub fn allows(&self, direction: Direction) -> bool {
+        match self {
+            Connectivity::Four => !direction.is_diagonal(),
+            Connectivity::Eight => true,
+        }
+    }
 }
 
 impl MovementComponent {
     pub fn new(position: (u16, u16)) -> MovementComponent {
         MovementComponent { 
             desired_position: position, 
+            path: None,
         }
     }
 
-    pub fn move_desired(&mut self, direction: Direction) {
-        match direction {
-            Direction::Up => {
-                //self.desired_position.0; 
-                self.desired_position.1 -= 1;
-            },
-            Direction::Down => {
-                //self.desired_position.0; 
-                self.desired_position.1 += 1;
-            },
-            Direction::Left => {
-                self.desired_position.0 -= 1; 
-                //self.desired_position.1;
+    // Sets desired to the next step of the path, a step is only
+    // dropped once reached so a blocked step is tried again.
+    // false if there is nothing left to follow
+    pub fn follow_path(&mut self, current: (u16, u16)) -> bool {
+        let path = match &mut self.path {
+            Some(path) => path,
+            None => return false,
+        };
+
+        if path.peek() == Some(current) {
+            path.next_step();
+        }
+
+        match path.peek() {
+            Some(step) => {
+                self.desired_position = step;
+                true
             },
-            Direction::Right => {
-                self.desired_position.0 += 1; 
-                //self.desired_position.1;
+            None => {
+                self.path = None;
+                false
             },
         }
     }
-}
\ No newline at end of file
+
+    // stays put rather than wrapping off the edge
+    pub fn move_desired(&mut self, direction: Direction) {
+        if let Some(position) = direction.step(self.desired_position) {
+            self.desired_position = position;
+        }
+    }
+}
+
+// Saving, fields in declaration order
+
+impl Persist for EnergyComponent {
+    fn save(&self, out: &mut SaveWriter) {
+        out.write(&self.speed);
+    }
+
+    fn load(input: &mut SaveReader) -> Result<Self> {
+        Ok(EnergyComponent::new(input.read()?))
+    }
+}
+
+impl Persist for HealthComponent {
+    fn save(&self, out: &mut SaveWriter) {
+        out.write(&self.current);
+        out.write(&self.max);
+    }
+
+    fn load(input: &mut SaveReader) -> Result<Self> {
+        Ok(HealthComponent {
+            current: input.read()?,
+            max: input.read()?,
+        })
+    }
+}
+
+impl Persist for AttackComponent {
+    fn save(&self, out: &mut SaveWriter) {
+        out.write(&self.power);
+    }
+
+    fn load(input: &mut SaveReader) -> Result<Self> {
+        Ok(AttackComponent::new(input.read()?))
+    }
+}
+
+impl Persist for DefenseComponent {
+    fn save(&self, out: &mut SaveWriter) {
+        out.write(&self.armor);
+    }
+
+    fn load(input: &mut SaveReader) -> Result<Self> {
+        Ok(DefenseComponent::new(input.read()?))
+    }
+}
+
+impl Persist for FactionComponent {
+    fn save(&self, out: &mut SaveWriter) {
+        let value: u8 = match self.faction {
+            Faction::Player => 0,
+            Faction::Monster => 1,
+        };
+        out.write(&value);
+    }
+
+    fn load(input: &mut SaveReader) -> Result<Self> {
+        match input.read::<u8>()? {
+            0 => Ok(FactionComponent::new(Faction::Player)),
+            1 => Ok(FactionComponent::new(Faction::Monster)),
+            value => Err(corrupt(&format!("bad faction {}", value))),
+        }
+    }
+}
+
+impl Persist for DoorComponent {
+    fn save(&self, out: &mut SaveWriter) {
+        out.write(&self.open);
+        out.write(&self.closed_character);
+        out.write(&self.open_character);
+    }
+
+    fn load(input: &mut SaveReader) -> Result<Self> {
+        Ok(DoorComponent {
+            open: input.read()?,
+            closed_character: input.read()?,
+            open_character: input.read()?,
+        })
+    }
+}
+
+impl Persist for PushableComponent {
+    fn save(&self, _out: &mut SaveWriter) {}
+
+    fn load(_input: &mut SaveReader) -> Result<Self> {
+        Ok(PushableComponent)
+    }
+}
+
+impl Persist for CollisionComponent {
+    fn save(&self, out: &mut SaveWriter) {
+        out.write(&self.position);
+        out.write(&self.matrix);
+        out.write(&self.layer);
+    }
+
+    fn load(input: &mut SaveReader) -> Result<Self> {
+        Ok(CollisionComponent {
+            position: input.read()?,
+            matrix: input.read()?,
+            layer: input.read()?,
+        })
+    }
+}
+
+impl Persist for RenderComponent {
+    fn save(&self, out: &mut SaveWriter) {
+        out.write(&self.character);
+        out.write(&self.backgroud);
+        out.write(&self.position_tl);
+        out.write(&self.matrix);
+        out.write(&self.visible);
+        out.write(&self.layer);
+    }
+
+    fn load(input: &mut SaveReader) -> Result<Self> {
+        Ok(RenderComponent {
+            character: input.read()?,
+            backgroud: input.read()?,
+            position_tl: input.read()?,
+            matrix: input.read()?,
+            visible: input.read()?,
+            layer: input.read()?,
+        })
+    }
+}
+
+impl Persist for MovementComponent {
+    fn save(&self, out: &mut SaveWriter) {
+        out.write(&self.desired_position);
+        out.write(&self.path);
+    }
+
+    fn load(input: &mut SaveReader) -> Result<Self> {
+        Ok(MovementComponent {
+            desired_position: input.read()?,
+            path: input.read()?,
+        })
+    }
+}
+
+impl Persist for Connectivity {
+    fn save(&self, out: &mut SaveWriter) {
+        let value: u8 = match self {
+            Connectivity::Four => 4,
+            Connectivity::Eight => 8,
+        };
+        out.write(&value);
+    }
+
+    fn load(input: &mut SaveReader) -> Result<Self> {
+        match input.read::<u8>()? {
+            4 => Ok(Connectivity::Four),
+            8 => Ok(Connectivity::Eight),
+            value => Err(corrupt(&format!("bad connectivity {}", value))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moved(position: (u16, u16), direction: Direction) -> (u16, u16) {
+        let mut movement = MovementComponent::new(position);
+        movement.move_desired(direction);
+        movement.desired_position
+    }
+
+    // the map edges are handle_movement's job, this only must not wrap
+    #[test]
+    fn move_desired_never_wraps() {
+        assert_eq!(moved((0, 5), Direction::Left), (0, 5));
+        assert_eq!(moved((5, 0), Direction::Up), (5, 0));
+        assert_eq!(moved((0, 0), Direction::UpLeft), (0, 0));
+        assert_eq!(moved((0, 5), Direction::DownLeft), (0, 5));
+        assert_eq!(moved((u16::MAX, 5), Direction::Right), (u16::MAX, 5));
+        assert_eq!(moved((5, u16::MAX), Direction::Down), (5, u16::MAX));
+        assert_eq!(moved((u16::MAX, u16::MAX), Direction::DownRight), (u16::MAX, u16::MAX));
+        assert_eq!(moved((5, 5), Direction::UpRight), (6, 4));
+    }
+}
@@ -0,0 +1,180 @@
+use crossterm::style::{Color, StyledContent, Stylize};
+
+use crate::a_star::Path;
+use crate::components::Direction;
+use crate::dijkstra_maps::DijkstraMap;
+use crate::enemy_ai::{AIType, Desire};
+use crate::renderer::Buffer;
+use crate::ui::UiLayer;
+
+// Views into the engine drawn over the frame, each one is a
+// Debug stage system so switching one is just enabling it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugOverlay {
+    Collision,
+    Dijkstra,
+    EntityIds,
+    AiIntents,
+}
+
+impl DebugOverlay {
+    pub const ALL: [DebugOverlay; 4] = [
+        DebugOverlay::Collision,
+        DebugOverlay::Dijkstra,
+        DebugOverlay::EntityIds,
+        DebugOverlay::AiIntents,
+    ];
+
+    pub fn system_name(&self) -> &'static str {
+        match self {
+            DebugOverlay::Collision => "debug_collision",
+            DebugOverlay::Dijkstra => "debug_dijkstra",
+            DebugOverlay::EntityIds => "debug_entity_ids",
+            DebugOverlay::AiIntents => "debug_ai_intents",
+        }
+    }
+
+    // for --overlay, the same names keymaps use after overlay_
+    pub fn from_name(name: &str) -> Option<DebugOverlay> {
+        match name {
+            "collision" => Some(DebugOverlay::Collision),
+            "dijkstra" => Some(DebugOverlay::Dijkstra),
+            "ids" => Some(DebugOverlay::EntityIds),
+            "intents" => Some(DebugOverlay::AiIntents),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DebugOverlay::Collision => "Collision",
+            DebugOverlay::Dijkstra => "Dijkstra",
+            DebugOverlay::EntityIds => "Entity id",
+            DebugOverlay::AiIntents => "AI intent",
+        }
+    }
+}
+
+// Overlays go under anything already on the layer, so the
+// hud and menus stay readable with every overlay on
+fn mark(layer: &mut UiLayer, screen: (u16, u16), content: StyledContent<char>) {
+    if layer.get((screen.0 as usize, screen.1 as usize)).is_none() {
+        layer.put(screen, content);
+    }
+}
+
+// map position to layer position, None when the camera can't see it
+fn to_screen(position: (u16, u16), offset: (u16, u16), layer: &UiLayer) -> Option<(u16, u16)> {
+    let x = position.0.checked_sub(offset.0)?;
+    let y = position.1.checked_sub(offset.1)?;
+
+    match (x as usize) < layer.width && (y as usize) < layer.height {
+        true => Some((x, y)),
+        false => None,
+    }
+}
+
+// the value of every blocked cell, on red
+pub fn draw_collision(layer: &mut UiLayer, offset: (u16, u16), buffer: &Buffer) {
+    for x in 0..layer.width {
+        for y in 0..layer.height {
+            let position = (offset.0 as usize + x, offset.1 as usize + y);
+            let value = match buffer.get(position.0).and_then(|col| col.get(position.1)) {
+                Some(value) if *value != 0 => *value,
+                _ => continue,
+            };
+
+            let label = std::char::from_digit(value as u32 % 36, 36).unwrap_or('?');
+            mark(layer, (x as u16, y as u16), label.with(Color::White).on(Color::DarkRed));
+        }
+    }
+}
+
+// Lighter is closer to a goal. The label is the distance in base 36,
+// + past that. Walls and cells nothing reaches are left alone
+pub fn draw_dijkstra(layer: &mut UiLayer, offset: (u16, u16), map: &DijkstraMap) {
+    for x in 0..layer.width {
+        for y in 0..layer.height {
+            let position = (offset.0 as usize + x, offset.1 as usize + y);
+            let value = match map.current_generation.get(position.0).and_then(|col| col.get(position.1)) {
+                Some(value) if *value < u32::MAX - 1 => *value,
+                _ => continue,
+            };
+
+            let label = std::char::from_digit(value, 36).unwrap_or('+');
+            let shade = Color::AnsiValue(255 - value.min(23) as u8);
+            mark(layer, (x as u16, y as u16), label.with(Color::Black).on(shade));
+        }
+    }
+}
+
+// text starting at a map position, cut off at the edge of the view
+pub fn draw_label(layer: &mut UiLayer, offset: (u16, u16), position: (u16, u16), text: &str, fg: Color) {
+    for (i, c) in text.chars().enumerate() {
+        let cell = (position.0.saturating_add(i as u16), position.1);
+        if let Some(screen) = to_screen(cell, offset, layer) {
+            mark(layer, screen, c.with(fg).on(Color::Black));
+        }
+    }
+}
+
+// where an entity is about to step, and the rest of its path
+pub fn draw_intent(layer: &mut UiLayer, offset: (u16, u16), position: (u16, u16), desired: (u16, u16), path: Option<&Path>) {
+    if let Some(path) = path {
+        for step in path.steps.iter() {
+            if let Some(screen) = to_screen(*step, offset, layer) {
+                mark(layer, screen, '·'.with(Color::Magenta).on(Color::Black));
+            }
+        }
+    }
+
+    let step = (desired.0 as i32 - position.0 as i32, desired.1 as i32 - position.1 as i32);
+    let arrow = match Direction::from_offset(step) {
+        Some(Direction::Up) => '↑',
+        Some(Direction::Down) => '↓',
+        Some(Direction::Left) => '←',
+        Some(Direction::Right) => '→',
+        Some(Direction::UpLeft) => '↖',
+        Some(Direction::UpRight) => '↗',
+        Some(Direction::DownLeft) => '↙',
+        Some(Direction::DownRight) => '↘',
+        None => return, // staying put, or teleporting
+    };
+    if let Some(screen) = to_screen(desired, offset, layer) {
+        mark(layer, screen, arrow.with(Color::Magenta).on(Color::Black));
+    }
+}
+
+// short enough to sit above the entity
+pub fn intent_name(ai_type: &AIType) -> String {
+    match ai_type {
+        AIType::SimpleDown => "down".to_string(),
+        AIType::SimpleLeft => "left".to_string(),
+        AIType::RollDownPlayer => "chase".to_string(),
+        AIType::SeekPosition(goal) => format!("seek {},{}", goal.0, goal.1),
+        AIType::Desires(desires) => desires
+            .iter()
+            .map(|(desire, weight)| match desire {
+                Desire::ChasePlayer => format!("chase{}", weight),
+                Desire::FleePlayer => format!("flee{}", weight),
+                Desire::Named(name) => format!("{}{}", name, weight),
+            })
+            .collect::<Vec<String>>()
+            .join("+"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::InputAction;
+
+    #[test]
+    fn overlay_names_match_the_keymap() {
+        for overlay in DebugOverlay::ALL.iter() {
+            let action = InputAction::ToggleOverlay(*overlay).name();
+            let name = action.strip_prefix("overlay_").unwrap();
+            assert_eq!(DebugOverlay::from_name(name), Some(*overlay));
+        }
+    }
+}
@@ -1,24 +1,68 @@
-use std::collections::VecDeque;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
 
 use crate::renderer::Buffer;
 use crate::renderer::Dimemsion;
+use crate::components::Connectivity;
+use crate::point::Point;
 
-/*
-IDEAS - 
- * redo to just do once over
-*/
+// Values at or below this are goals the map flows down to
+pub const GOAL_THRESHOLD: u32 = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InfluenceKind {
+    Value, // sets the map value, u32::MAX is a wall, low values are goals
+    Cost,  // what it costs to step onto the cells, 1 is normal
+}
 
 pub struct Influence {
     pub position: Dimemsion,
-    pub matrix: Buffer,
+    pub matrix: Buffer, // 1 where the influence applies
     pub value: u32,
+    pub kind: InfluenceKind,
+}
+
+impl Influence {
+    pub fn value(position: Dimemsion, matrix: Buffer, value: u32) -> Influence {
+        Influence {
+            position,
+            matrix,
+            value,
+            kind: InfluenceKind::Value,
+        }
+    }
+
+    pub fn cost(position: Dimemsion, matrix: Buffer, cost: u32) -> Influence {
+        Influence {
+            position,
+            matrix,
+            value: cost,
+            kind: InfluenceKind::Cost,
+        }
+    }
+
+    // the cells it applies to, anything hanging off the edge is cut off
+    pub fn cells(&self, dimensions: (usize, usize)) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let (column, row): (usize, usize) = (self.position.0.into(), self.position.1.into());
+
+        self.matrix
+            .iter()
+            .enumerate()
+            .flat_map(move |(x, col)| {
+                col.iter()
+                    .enumerate()
+                    .filter(|(_, value)| **value == 1)
+                    .map(move |(y, _)| (column + x, row + y))
+            })
+            .filter(move |(x, y)| *x < dimensions.0 && *y < dimensions.1)
+    }
 }
 
 pub struct DijkstraMap {
     pub current_generation: Vec<Vec<u32>>,
     dimensions: (usize, usize),
     pub influences: Vec<Influence>,
-    
+    pub connectivity: Connectivity, // Eight lets values flow diagonally too
 }
 
 impl DijkstraMap {
@@ -27,40 +71,133 @@ impl DijkstraMap {
             current_generation: vec![vec![u32::MAX - 1; dimensions.1 as usize]; dimensions.0 as usize],
             dimensions: (dimensions.0 as usize, dimensions.1 as usize),
             influences,
+            connectivity: Connectivity::Four,
+        }
+    }
+
+    pub fn apply_influences(&mut self) {
+        for influence in self.influences.iter().filter(|i| i.kind == InfluenceKind::Value) {
+            for (x, y) in influence.cells(self.dimensions) {
+                self.current_generation[x][y] = influence.value;
+            }
         }
     }
 
-    pub fn make_render(&self) -> Buffer {
-        let mut result: Buffer = vec![vec![u8::MAX; self.dimensions.1]; self.dimensions.0];
+    // One pass from every goal at once. Plain BFS when every step
+    // costs 1 and the goals agree on a value, priority queue otherwise
+    pub fn new_implementation(&mut self) {
+        self.current_generation = vec![vec![u32::MAX - 1; self.dimensions.1]; self.dimensions.0];
 
-        for (x, col) in self.current_generation.iter().enumerate() {
-            for (y, val) in col.iter().enumerate() {
-                if *val > 25 {
-                    result[x][y] = 5 as u8;
-                } else {
-                    result[x][y] = (*val as u8) + 8;
+        self.apply_influences();
+
+        let costs = self.build_costs();
+        // goals keep their own value, like in flood_per_source
+        let mut fixed: Vec<Vec<bool>> = vec![vec![false; self.dimensions.1]; self.dimensions.0];
+        let mut seeds: Vec<Dimemsion> = Vec::new();
+
+        for influence in self.influences.iter().filter(|i| i.kind == InfluenceKind::Value) {
+            if influence.value > GOAL_THRESHOLD { continue; }
+
+            for (x, y) in influence.cells(self.dimensions) {
+                if !fixed[x][y] {
+                    fixed[x][y] = true;
+                    seeds.push((x as u16, y as u16));
                 }
             }
         }
 
-        return result;
+        let same_start = seeds
+            .windows(2)
+            .all(|pair| self.value_at(pair[0]) == self.value_at(pair[1]));
+
+        match costs {
+            None if same_start => self.scan_uniform(seeds, fixed),
+            _ => self.scan_weighted(seeds, fixed, costs),
+        }
     }
 
-    pub fn apply_influences(&mut self) {
-        for influence in &self.influences {
-            let (column, row): (usize, usize) = (influence.position.0.into(), influence.position.1.into());
+    fn value_at(&self, position: Dimemsion) -> u32 {
+        self.current_generation[position.0 as usize][position.1 as usize]
+    }
 
-            for (x, col) in influence.matrix.iter().enumerate() {
-                for (y, value) in col.iter().enumerate() {
-                    if *value == 1 {
-                        self.current_generation[column + x][row + y] = influence.value;
-                    }
+    // None if every cell costs 1
+    fn build_costs(&self) -> Option<Vec<Vec<u32>>> {
+        let mut costs: Option<Vec<Vec<u32>>> = None;
+
+        for influence in self.influences.iter().filter(|i| i.kind == InfluenceKind::Cost) {
+            let costs = costs.get_or_insert_with(|| vec![vec![1; self.dimensions.1]; self.dimensions.0]);
+
+            for (x, y) in influence.cells(self.dimensions) {
+                costs[x][y] = influence.value;
+            }
+        }
+
+        costs
+    }
+
+    fn scan_uniform(&mut self, seeds: Vec<Dimemsion>, fixed: Vec<Vec<bool>>) {
+        let mut done = fixed;
+        let mut queue: VecDeque<Dimemsion> = seeds.into_iter().collect();
+
+        while let Some(target) = queue.pop_front() {
+            let target_value = self.value_at(target);
+
+            for neighbour in self.neighbours(target) {
+                let position = (neighbour.0 as usize, neighbour.1 as usize);
+
+                if self.current_generation[position.0][position.1] == u32::MAX || done[position.0][position.1] {
+                    continue;
+                }
+                queue.push_back(neighbour);
+                done[position.0][position.1] = true;
+
+                if self.current_generation[position.0][position.1] > target_value + 1 {
+                    self.current_generation[position.0][position.1] = target_value + 1;
                 }
             }
         }
     }
 
-    pub fn new_implementation(&mut self) {
+    fn scan_weighted(&mut self, seeds: Vec<Dimemsion>, fixed: Vec<Vec<bool>>, costs: Option<Vec<Vec<u32>>>) {
+        let mut queue: BinaryHeap<Reverse<(u32, Dimemsion)>> = seeds
+            .into_iter()
+            .map(|position| Reverse((self.value_at(position), position)))
+            .collect();
+
+        while let Some(Reverse((target_value, target))) = queue.pop() {
+            if target_value > self.value_at(target) {
+                continue; // found a better way here already
+            }
+
+            for neighbour in self.neighbours(target) {
+                let position = (neighbour.0 as usize, neighbour.1 as usize);
+
+                if self.current_generation[position.0][position.1] == u32::MAX || fixed[position.0][position.1] {
+                    continue;
+                }
+
+                let step = match &costs {
+                    Some(costs) => costs[position.0][position.1],
+                    None => 1,
+                };
+                let value = target_value.saturating_add(step).min(u32::MAX - 1);
+
+                if value < self.current_generation[position.0][position.1] {
+                    self.current_generation[position.0][position.1] = value;
+                    queue.push(Reverse((value, neighbour)));
+                }
+            }
+        }
+    }
+
+    fn neighbours(&self, target: Dimemsion) -> Vec<Dimemsion> {
+        grid_neighbours(target, self.dimensions, self.connectivity)
+    }
+
+    // The old way, a full flood per goal cell. Kept to check
+    // new_implementation against, see the tests
+    #[cfg(test)]
+    pub fn flood_per_source(&mut self) {
         self.current_generation = vec![vec![u32::MAX - 1; self.dimensions.1]; self.dimensions.0];
 
         self.apply_influences();
@@ -71,18 +208,11 @@ impl DijkstraMap {
         let mut zero_positions: Vec<Dimemsion> = Vec::new();
 
 
-        for influence in &self.influences {
-            if influence.value > 100 { continue; }
+        for influence in self.influences.iter().filter(|i| i.kind == InfluenceKind::Value) {
+            if influence.value > GOAL_THRESHOLD { continue; }
 
-            let start = influence.position;
-            
-            for (x, col) in influence.matrix.iter().enumerate() {
-                for (y, val) in col.iter().enumerate() {
-                    if *val == 1 {
-                        let position = (start.0 + x as u16, start.1 + y as u16);
-                        zero_positions.push(position);
-                    }
-                }
+            for (x, y) in influence.cells(self.dimensions) {
+                zero_positions.push((x as u16, y as u16));
             }
         }   
 
@@ -121,14 +251,13 @@ impl DijkstraMap {
         }
     }
 
+    #[cfg(test)]
     fn get_neighbours(&self, target: Dimemsion) -> (Vec<(Dimemsion, u32)>, u32) {
         let mut neighbours = Vec::new();
-        let four_dir = vec![(0, 1), (1, 0), (1, 2), (2, 1)];
 
         let mut lowest: u32 = u32::MAX;
 
-        for dir in &four_dir {
-            let location = (target.0 + dir.0 - 1, target.1 + dir.1 - 1);
+        for location in self.neighbours(target) {
             let value = self.current_generation[location.0 as usize][location.1 as usize];
             if value < lowest {
                 lowest = value;
@@ -142,3 +271,231 @@ impl DijkstraMap {
         return (neighbours, lowest);
     }
 }
+
+// never off the map, diagonal steps cost the same as straight ones
+fn grid_neighbours(target: Dimemsion, dimensions: (usize, usize), connectivity: Connectivity) -> Vec<Dimemsion> {
+    Point::from(target)
+        .neighbours(connectivity, dimensions)
+        .into_iter()
+        .map(|point| point.into())
+        .collect()
+}
+
+// Signed version of a Dijkstra map, so it can be flipped into
+// a flee map and added to others. Walls stay walls through it all
+pub const DESIRE_WALL: i32 = i32::MAX;
+// cells no goal can reach, big but safe to add up
+pub const DESIRE_UNREACHABLE: i32 = 10_000;
+
+#[derive(Clone, Debug)]
+pub struct DesireMap {
+    pub values: Vec<Vec<i32>>,
+    dimensions: (usize, usize),
+    connectivity: Connectivity,
+}
+
+impl DesireMap {
+    pub fn from_dijkstra(map: &DijkstraMap) -> DesireMap {
+        let values = map.current_generation
+            .iter()
+            .map(|col| col.iter().map(|val| match *val {
+                u32::MAX => DESIRE_WALL,
+                val if val >= DESIRE_UNREACHABLE as u32 => DESIRE_UNREACHABLE,
+                val => val as i32,
+            }).collect())
+            .collect();
+
+        DesireMap {
+            values,
+            dimensions: map.dimensions,
+            connectivity: map.connectivity,
+        }
+    }
+
+    // The classic flee map: scale by a negative coefficient (-1.2 is
+    // the usual) then rescan, so fleeing heads for open space rather
+    // than into the nearest corner
+    pub fn flee(map: &DijkstraMap, coefficient: f32) -> DesireMap {
+        let mut result = DesireMap::from_dijkstra(map);
+
+        for col in result.values.iter_mut() {
+            for val in col.iter_mut() {
+                if *val != DESIRE_WALL && *val != DESIRE_UNREACHABLE {
+                    *val = (*val as f32 * coefficient).round() as i32;
+                }
+            }
+        }
+
+        result.rescan();
+        result
+    }
+
+    pub fn value_at(&self, position: Dimemsion) -> Option<i32> {
+        match self.values.get(position.0 as usize) {
+            Some(col) => match col.get(position.1 as usize) {
+                Some(val) if *val != DESIRE_WALL => Some(*val),
+                _ => None,
+            },
+            None => None,
+        }
+    }
+
+    // every cell ends up at most one more than its lowest neighbour
+    fn rescan(&mut self) {
+        let mut queue: BinaryHeap<Reverse<(i32, Dimemsion)>> = BinaryHeap::new();
+
+        for (x, col) in self.values.iter().enumerate() {
+            for (y, val) in col.iter().enumerate() {
+                if *val != DESIRE_WALL {
+                    queue.push(Reverse((*val, (x as u16, y as u16))));
+                }
+            }
+        }
+
+        while let Some(Reverse((target_value, target))) = queue.pop() {
+            if target_value > self.values[target.0 as usize][target.1 as usize] {
+                continue;
+            }
+
+            for neighbour in grid_neighbours(target, self.dimensions, self.connectivity) {
+                let current = self.values[neighbour.0 as usize][neighbour.1 as usize];
+
+                if current != DESIRE_WALL && target_value + 1 < current {
+                    self.values[neighbour.0 as usize][neighbour.1 as usize] = target_value + 1;
+                    queue.push(Reverse((target_value + 1, neighbour)));
+                }
+            }
+        }
+    }
+}
+
+// None if a wall in any of the maps
+pub fn weighted_sum(maps: &[(&DesireMap, f32)], position: Dimemsion) -> Option<i32> {
+    let mut total: f32 = 0.0;
+
+    for (map, weight) in maps {
+        match map.value_at(position) {
+            Some(val) => total += val as f32 * weight,
+            None => return None,
+        }
+    }
+
+    Some(total.round() as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_generation::CaveMapGenerator;
+    use crate::rng::GameRng;
+
+    const DIMENSIONS: Dimemsion = (200, 100);
+
+    // a cave sized like the game's, the same one every run
+    fn cave_walls() -> Buffer {
+        let mut generator = CaveMapGenerator::new(DIMENSIONS);
+        generator.randomize(0.45, &mut GameRng::new(0));
+        generator.generate();
+        generator.current_generation // 1 is a wall already
+    }
+
+    // 4 goal cells in a row somewhere open
+    fn open_goal(walls: &Buffer) -> Dimemsion {
+        for (x, col) in walls.iter().enumerate().take(150).skip(50) {
+            for y in 25..75 {
+                if col[y..y + 4].iter().all(|v| *v == 0) {
+                    return (x as u16, y as u16);
+                }
+            }
+        }
+        panic!("no open spot in the cave");
+    }
+
+    fn cave_map(walls: &Buffer, goals: &[Dimemsion]) -> DijkstraMap {
+        let mut influences = vec![Influence::value((0, 0), walls.clone(), u32::MAX)];
+        for goal in goals {
+            influences.push(Influence::value(*goal, vec![vec![1; 4]], 0));
+        }
+        DijkstraMap::new(DIMENSIONS, influences)
+    }
+
+    #[test]
+    fn single_pass_matches_the_old_flood() {
+        let walls = cave_walls();
+        let goal = open_goal(&walls);
+
+        for connectivity in [Connectivity::Four, Connectivity::Eight].iter() {
+            let mut old_map = cave_map(&walls, &[goal]);
+            let mut new_map = cave_map(&walls, &[goal]);
+            old_map.connectivity = *connectivity;
+            new_map.connectivity = *connectivity;
+            old_map.flood_per_source();
+            new_map.new_implementation();
+
+            assert_eq!(old_map.current_generation, new_map.current_generation);
+        }
+    }
+
+    #[test]
+    fn costly_cells_count_on_the_way_through() {
+        let mut map = DijkstraMap::new((5, 1), vec![
+            Influence::value((0, 0), vec![vec![1]], 0),
+            Influence::cost((2, 0), vec![vec![1]], 3),
+        ]);
+        map.new_implementation();
+
+        assert_eq!(map.current_generation, vec![vec![0], vec![1], vec![4], vec![5], vec![6]]);
+    }
+
+    // goals, walls and costs hanging off the edge are cut off, not a panic
+    #[test]
+    fn influences_off_the_edge_are_cut_off() {
+        let influences = || vec![
+            Influence::value((4, 1), vec![vec![1; 3]; 2], 0),
+            Influence::value((2, 1), vec![vec![0, 1, 1]], u32::MAX),
+            Influence::cost((1, 0), vec![vec![1; 5]], 2),
+        ];
+        let mut map = DijkstraMap::new((5, 3), influences());
+        map.new_implementation();
+
+        assert_eq!(map.current_generation, vec![
+            vec![6, 5, 6],
+            vec![5, 4, 6], // this column costs 2
+            vec![3, 2, u32::MAX],
+            vec![2, 1, 1],
+            vec![1, 0, 0],
+        ]);
+
+        // the old flood has no costs, but cuts off the same way
+        let mut old_map = DijkstraMap::new((5, 3), influences());
+        old_map.flood_per_source();
+        assert_eq!(old_map.current_generation[4], vec![1, 0, 0]);
+    }
+
+    // cargo test --release -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_single_pass_against_old_flood() {
+        let walls = cave_walls();
+        let goal = open_goal(&walls);
+        let runs = 20;
+
+        let mut old_map = cave_map(&walls, &[goal]);
+        let start = std::time::Instant::now();
+        for _ in 0..runs {
+            old_map.flood_per_source();
+        }
+        let old_time = start.elapsed() / runs;
+
+        let mut new_map = cave_map(&walls, &[goal]);
+        let start = std::time::Instant::now();
+        for _ in 0..runs {
+            new_map.new_implementation();
+        }
+        let new_time = start.elapsed() / runs;
+
+        println!("flood_per_source:   {:?} per map", old_time);
+        println!("new_implementation: {:?} per map", new_time);
+        println!("speedup: {:.1}x", old_time.as_secs_f64() / new_time.as_secs_f64());
+    }
+}
@@ -1,15 +1,33 @@
 use crate::components::Direction;
 use crate::renderer::Buffer;
+use crate::tilemap::TileMap;
+use crate::components::Connectivity;
+use crate::a_star::{find_path, Path, PathOptions};
+use crate::dijkstra_maps::{weighted_sum, DesireMap};
+use crate::save::{corrupt, Persist, SaveReader, SaveWriter};
+use crate::rng::GameRng;
+use crate::scheduler::{MOVE_COST, WAIT_COST};
 use rand::prelude::*;
+use std::io::Result;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum AIType {
     SimpleDown,
     SimpleLeft,
     RollDownPlayer,
+    SeekPosition((u16, u16)), // A* to a fixed spot
+    Desires(Vec<(Desire, f32)>), // roll down a weighted mix of maps
 }
 
-#[derive(Clone, Debug)]
+// Which desire map, GameState keeps one of each
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Desire {
+    ChasePlayer,
+    FleePlayer,
+    Named(String), // game code adds its own, eg. "light" or "items"
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct EnemyAIComponent {
     pub ai_type: AIType,
 }
@@ -26,66 +44,215 @@ impl EnemyAIComponent {
 pub enum Action {
     DoNothing,
     Move(Direction),
+    FollowPath, // take the next step of the movement path
 }
 
-pub fn simple_down(position: (u16, u16), collision_buffer: &Buffer) -> (Action, u8) {
+// walls from the map, entities from the collision buffer,
+// off the map counts as blocked
+fn is_blocked(position: (u16, u16), collision_buffer: &Buffer, tilemap: &TileMap) -> bool {
+    !tilemap.in_bounds(position) || !tilemap.is_walkable(position) || collision_buffer[position.0 as usize][position.1 as usize] == 1
+}
+
+pub fn simple_down(position: (u16, u16), collision_buffer: &Buffer, tilemap: &TileMap) -> (Action, u8) {
+    let below = match tilemap.step(position, Direction::Down) {
+        Some(below) => below,
+        None => return (Action::DoNothing, WAIT_COST), // bottom of the map
+    };
+
     // cant move down
-    if collision_buffer[position.0 as usize - 1][position.1 as usize] == 1 {
-        return (Action::DoNothing, 10);
+    if is_blocked(below, collision_buffer, tilemap) {
+        return (Action::DoNothing, WAIT_COST);
     } else { // can move down
-        return (Action::Move(Direction::Down), 10);
+        return (Action::Move(Direction::Down), MOVE_COST);
     }
 }
 
-pub fn roll_down_player(position: (u16, u16), collision_buffer: &Buffer, player_dijk: &Vec<Vec<u32>>) -> (Action, u8) {
-    let lowest_direction = lowest_direction(position, player_dijk);
+// Walks a path to the goal over several turns, only
+// searches again when there is no path or a wall is in the way
+pub fn seek_position(position: (u16, u16), goal: (u16, u16), path: &mut Option<Path>, collision_buffer: &Buffer, tilemap: &TileMap, connectivity: Connectivity) -> (Action, u8) {
+    if position == goal {
+        *path = None;
+        return (Action::DoNothing, WAIT_COST);
+    }
+
+    let stale = match path {
+        Some(current) => current.goal() != Some(goal) || match current.peek() {
+            Some(step) => !tilemap.is_walkable(step),
+            None => true,
+        },
+        None => true,
+    };
+
+    if stale {
+        let options = PathOptions::new(connectivity);
+        *path = find_path(position, goal, tilemap.dimensions, |p| tilemap.move_cost(p), &options);
+    }
+
+    let current = match path {
+        Some(current) => current,
+        None => return (Action::DoNothing, WAIT_COST),
+    };
+
+    // first step might be where we already are
+    let next = current.steps.iter().find(|step| **step != position);
+
+    match next {
+        // someone standing there, wait for them to move
+        Some(step) if is_blocked(*step, collision_buffer, tilemap) => (Action::DoNothing, WAIT_COST),
+        Some(_) => (Action::FollowPath, MOVE_COST),
+        None => (Action::DoNothing, WAIT_COST),
+    }
+}
+
+pub fn roll_down_player(position: (u16, u16), collision_buffer: &Buffer, tilemap: &TileMap, player_dijk: &[Vec<u32>], connectivity: Connectivity, rng: &mut GameRng) -> (Action, u8) {
+    let lowest_direction = lowest_direction(position, player_dijk, connectivity, rng);
 
     match lowest_direction {
         Some(direction) => {
-            if collision_buffer[(position.0 + direction.0.0 - 1) as usize][(position.1 + direction.0.1 - 1) as usize] == 1 {
-                return (Action::DoNothing, 10);
+            // walk into the player anyway, the bump is the attack
+            let at_player = player_dijk[direction.0.0 as usize][direction.0.1 as usize] == 0;
+
+            if at_player {
+                (Action::Move(direction.1), MOVE_COST)
+            } else if is_blocked(direction.0, collision_buffer, tilemap) {
+                (Action::DoNothing, WAIT_COST)
             } else { // can move in direction
-                return (Action::Move(direction.1), 10);
+                (Action::Move(direction.1), MOVE_COST)
             }
         },
-        None => return (Action::DoNothing, 10),
+        None => (Action::DoNothing, WAIT_COST),
     }
 }
 
-fn lowest_direction(target: (u16, u16), dijk: &Vec<Vec<u32>>) -> Option<((u16, u16), Direction)> {
-    let four_pos = vec![(0, 1), (1, 0), (1, 2), (2, 1)];
-    let four_dir = vec![Direction::Left, Direction::Up, Direction::Down, Direction::Right];
+// Like roll_down_player, but over the weighted sum of several maps.
+// Stays put when nothing around is better than here
+pub fn roll_down_desires(position: (u16, u16), collision_buffer: &Buffer, tilemap: &TileMap, maps: &[(&DesireMap, f32)], connectivity: Connectivity) -> (Action, u8) {
+    let here = weighted_sum(maps, position).unwrap_or(i32::MAX);
+
+    let mut best: Option<(i32, Direction)> = None;
+
+    for direction in connectivity.directions() {
+        let target = match tilemap.step(position, *direction) {
+            Some(target) => target,
+            None => continue,
+        };
+
+        if is_blocked(target, collision_buffer, tilemap) {
+            continue;
+        }
+
+        let value = match weighted_sum(maps, target) {
+            Some(val) => val,
+            None => continue,
+        };
+
+        let better = match best {
+            Some((best_value, _)) => value < best_value,
+            None => true,
+        };
+        if value < here && better {
+            best = Some((value, *direction));
+        }
+    }
 
+    match best {
+        Some((_, direction)) => (Action::Move(direction), MOVE_COST),
+        None => (Action::DoNothing, WAIT_COST),
+    }
+}
+
+fn lowest_direction(target: (u16, u16), dijk: &[Vec<u32>], connectivity: Connectivity, rng: &mut GameRng) -> Option<((u16, u16), Direction)> {
     let mut lowest = u32::MAX;
 
-    let four_values: Vec<(usize, u32)> = four_pos
+    let values: Vec<((u16, u16), Direction, u32)> = connectivity
+        .directions()
         .iter()
-        .enumerate()
-        .map(|(i, x)| (i, dijk[(x.0 + target.0 - 1) as usize][(x.1 + target.1 - 1) as usize]))
+        .filter_map(|direction| {
+            let position = direction.step(target)?;
+            let value = *dijk.get(position.0 as usize)?.get(position.1 as usize)?;
+            Some((position, *direction, value))
+        })
         .collect();
 
-    for (_, x) in four_values.iter() {
+    for (_, _, x) in values.iter() {
         if lowest > *x {
             lowest = *x;
         }
     }
 
-    let equal_lowest: Vec<&(usize, u32)> = four_values
+    let equal_lowest: Vec<&((u16, u16), Direction, u32)> = values
         .iter()
-        .filter(|(_i, x)| x <= &lowest)
+        .filter(|(_, _, x)| x <= &lowest)
         .collect();
 
-    let mut rng = thread_rng();
-
     match equal_lowest.len() {
-        1 => return Some((four_pos[equal_lowest[0].0], four_dir[equal_lowest[0].0])),
-        2 | 3 | 4 => {
+        0 => None,
+        1 => Some((equal_lowest[0].0, equal_lowest[0].1)),
+        _ => {
             let random_idx = rng.gen_range(0..equal_lowest.len());
 
-            return Some(
-                (four_pos[equal_lowest[random_idx].0], four_dir[equal_lowest[random_idx].0])
-            );
+            Some((equal_lowest[random_idx].0, equal_lowest[random_idx].1))
         },
-        _ => return None,
+    }
+}
+
+impl Persist for Desire {
+    fn save(&self, out: &mut SaveWriter) {
+        match self {
+            Desire::ChasePlayer => out.write(&0u8),
+            Desire::FleePlayer => out.write(&1u8),
+            Desire::Named(name) => {
+                out.write(&2u8);
+                out.write(name);
+            },
+        }
+    }
+
+    fn load(input: &mut SaveReader) -> Result<Self> {
+        match input.read::<u8>()? {
+            0 => Ok(Desire::ChasePlayer),
+            1 => Ok(Desire::FleePlayer),
+            2 => Ok(Desire::Named(input.read()?)),
+            value => Err(corrupt(&format!("bad desire {}", value))),
+        }
+    }
+}
+
+impl Persist for AIType {
+    fn save(&self, out: &mut SaveWriter) {
+        match self {
+            AIType::SimpleDown => out.write(&0u8),
+            AIType::SimpleLeft => out.write(&1u8),
+            AIType::RollDownPlayer => out.write(&2u8),
+            AIType::SeekPosition(goal) => {
+                out.write(&3u8);
+                out.write(goal);
+            },
+            AIType::Desires(desires) => {
+                out.write(&4u8);
+                out.write(desires);
+            },
+        }
+    }
+
+    fn load(input: &mut SaveReader) -> Result<Self> {
+        match input.read::<u8>()? {
+            0 => Ok(AIType::SimpleDown),
+            1 => Ok(AIType::SimpleLeft),
+            2 => Ok(AIType::RollDownPlayer),
+            3 => Ok(AIType::SeekPosition(input.read()?)),
+            4 => Ok(AIType::Desires(input.read()?)),
+            value => Err(corrupt(&format!("bad ai type {}", value))),
+        }
+    }
+}
+
+impl Persist for EnemyAIComponent {
+    fn save(&self, out: &mut SaveWriter) {
+        out.write(&self.ai_type);
+    }
+
+    fn load(input: &mut SaveReader) -> Result<Self> {
+        Ok(EnemyAIComponent::new(input.read()?))
     }
 }
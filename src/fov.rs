@@ -0,0 +1,264 @@
+use std::io::Result;
+
+use crate::tilemap::TileMap;
+use crate::save::{corrupt, Persist, SaveReader, SaveWriter};
+
+// style map slot for tiles never seen
+pub const UNSEEN_GLYPH: u8 = 36;
+
+// multipliers to turn one octant into the other seven
+const OCTANTS: [(i32, i32, i32, i32); 8] = [
+    (1, 0, 0, 1),
+    (0, 1, 1, 0),
+    (0, -1, 1, 0),
+    (-1, 0, 0, 1),
+    (-1, 0, 0, -1),
+    (0, -1, -1, 0),
+    (0, 1, -1, 0),
+    (1, 0, 0, -1),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Visibility {
+    Unseen,
+    Remembered, // seen before, not right now
+    Visible,
+}
+
+// What the player can see, recursive shadowcasting over tile opacity
+#[derive(Debug, PartialEq)]
+pub struct FieldOfView {
+    pub radius: u16,
+    pub enabled: bool, // off means everything is visible
+    dimensions: (usize, usize),
+    states: Vec<Vec<Visibility>>,
+}
+
+impl FieldOfView {
+    pub fn new(dimensions: (u16, u16), radius: u16) -> FieldOfView {
+        FieldOfView {
+            radius,
+            enabled: true,
+            dimensions: (dimensions.0 as usize, dimensions.1 as usize),
+            states: vec![vec![Visibility::Unseen; dimensions.1 as usize]; dimensions.0 as usize],
+        }
+    }
+
+    pub fn dimensions(&self) -> (usize, usize) {
+        self.dimensions
+    }
+
+    pub fn get(&self, position: (u16, u16)) -> Visibility {
+        if !self.enabled {
+            return Visibility::Visible;
+        }
+
+        match self.states.get(position.0 as usize) {
+            Some(col) => match col.get(position.1 as usize) {
+                Some(state) => *state,
+                None => Visibility::Unseen,
+            },
+            None => Visibility::Unseen,
+        }
+    }
+
+    pub fn is_visible(&self, position: (u16, u16)) -> bool {
+        self.get(position) == Visibility::Visible
+    }
+
+    // forget everything, eg. on a new map
+    pub fn reset(&mut self) {
+        for col in self.states.iter_mut() {
+            for state in col.iter_mut() {
+                *state = Visibility::Unseen;
+            }
+        }
+    }
+
+    pub fn compute(&mut self, origin: (u16, u16), tilemap: &TileMap) {
+        // what was visible is now only remembered
+        for col in self.states.iter_mut() {
+            for state in col.iter_mut() {
+                if *state == Visibility::Visible {
+                    *state = Visibility::Remembered;
+                }
+            }
+        }
+
+        self.mark_visible(origin.0 as i32, origin.1 as i32);
+
+        for mult in OCTANTS.iter() {
+            self.cast_light(tilemap, (origin.0 as i32, origin.1 as i32), 1, 1.0, 0.0, *mult);
+        }
+    }
+
+    // scans rows moving away from the origin, start and end are
+    // the slopes still lit, recurses when a wall splits the light
+    fn cast_light(&mut self, tilemap: &TileMap, origin: (i32, i32), row: i32, start: f32, end: f32, mult: (i32, i32, i32, i32)) {
+        if start < end {
+            return;
+        }
+
+        let radius = self.radius as i32;
+        let (xx, xy, yx, yy) = mult;
+        let mut start = start;
+        let mut new_start = 0.0;
+
+        for j in row..=radius {
+            let dy = -j;
+            let mut dx = -j - 1;
+            let mut blocked = false;
+
+            while dx <= 0 {
+                dx += 1;
+
+                let x = origin.0 + dx * xx + dy * xy;
+                let y = origin.1 + dx * yx + dy * yy;
+                let left_slope = (dx as f32 - 0.5) / (dy as f32 + 0.5);
+                let right_slope = (dx as f32 + 0.5) / (dy as f32 - 0.5);
+
+                if start < right_slope {
+                    continue;
+                } else if end > left_slope {
+                    break;
+                }
+
+                if dx * dx + dy * dy <= radius * radius {
+                    self.mark_visible(x, y);
+                }
+
+                let opaque = x < 0 || y < 0 || tilemap.is_opaque((x as u16, y as u16));
+
+                if blocked {
+                    if opaque {
+                        new_start = right_slope;
+                        continue;
+                    }
+                    blocked = false;
+                    start = new_start;
+                } else if opaque && j < radius {
+                    blocked = true;
+                    self.cast_light(tilemap, origin, j + 1, start, left_slope, mult);
+                    new_start = right_slope;
+                }
+            }
+
+            if blocked {
+                break;
+            }
+        }
+    }
+
+    fn mark_visible(&mut self, x: i32, y: i32) {
+        if x >= 0 && y >= 0 && (x as usize) < self.dimensions.0 && (y as usize) < self.dimensions.1 {
+            self.states[x as usize][y as usize] = Visibility::Visible;
+        }
+    }
+}
+
+impl Persist for Visibility {
+    fn save(&self, out: &mut SaveWriter) {
+        let value: u8 = match self {
+            Visibility::Unseen => 0,
+            Visibility::Remembered => 1,
+            Visibility::Visible => 2,
+        };
+        out.write(&value);
+    }
+
+    fn load(input: &mut SaveReader) -> Result<Self> {
+        match input.read::<u8>()? {
+            0 => Ok(Visibility::Unseen),
+            1 => Ok(Visibility::Remembered),
+            2 => Ok(Visibility::Visible),
+            value => Err(corrupt(&format!("bad visibility {}", value))),
+        }
+    }
+}
+
+impl Persist for FieldOfView {
+    fn save(&self, out: &mut SaveWriter) {
+        out.write(&self.radius);
+        out.write(&self.enabled);
+        out.write(&self.dimensions);
+        out.write(&self.states);
+    }
+
+    fn load(input: &mut SaveReader) -> Result<Self> {
+        let radius = input.read()?;
+        let enabled = input.read()?;
+        let dimensions: (usize, usize) = input.read()?;
+        let states: Vec<Vec<Visibility>> = input.read()?;
+
+        if states.len() != dimensions.0 || states.iter().any(|col| col.len() != dimensions.1) {
+            return Err(corrupt("field of view does not match its size"));
+        }
+
+        Ok(FieldOfView {
+            radius,
+            enabled,
+            dimensions,
+            states,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tilemap::MapTile;
+
+    fn open_map() -> TileMap {
+        TileMap::new((20, 20))
+    }
+
+    #[test]
+    fn sight_stops_at_the_radius() {
+        let mut fov = FieldOfView::new((20, 20), 5);
+        fov.compute((10, 10), &open_map());
+
+        assert!(fov.is_visible((10, 10)));
+        assert!(fov.is_visible((14, 10)));
+        assert!(fov.is_visible((13, 13)));
+        assert_eq!(fov.get((16, 10)), Visibility::Unseen);
+        assert_eq!(fov.get((14, 14)), Visibility::Unseen);
+    }
+
+    // the wall itself is seen, what is behind it isn't
+    #[test]
+    fn walls_block_sight() {
+        let mut tilemap = open_map();
+        tilemap.fill_rect((12, 5), (12, 15), MapTile::wall());
+        let mut fov = FieldOfView::new((20, 20), 8);
+        fov.compute((10, 10), &tilemap);
+
+        assert!(fov.is_visible((11, 10)));
+        assert!(fov.is_visible((12, 10)));
+        assert_eq!(fov.get((13, 10)), Visibility::Unseen);
+        assert_eq!(fov.get((16, 11)), Visibility::Unseen);
+        // the other way is open
+        assert!(fov.is_visible((4, 10)));
+    }
+
+    #[test]
+    fn seen_tiles_are_remembered() {
+        let mut fov = FieldOfView::new((20, 20), 5);
+        fov.compute((10, 10), &open_map());
+        fov.compute((3, 3), &open_map());
+
+        assert!(fov.is_visible((3, 3)));
+        assert_eq!(fov.get((14, 10)), Visibility::Remembered);
+        assert_eq!(fov.get((18, 18)), Visibility::Unseen);
+
+        fov.reset();
+        assert_eq!(fov.get((14, 10)), Visibility::Unseen);
+    }
+
+    #[test]
+    fn disabled_sees_everything() {
+        let mut fov = FieldOfView::new((20, 20), 5);
+        fov.enabled = false;
+
+        assert!(fov.is_visible((18, 18)));
+    }
+}
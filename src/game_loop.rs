@@ -0,0 +1,197 @@
+use std::time::{Duration, Instant};
+
+use crossterm::{
+    Result,
+    event::{read, poll, Event},
+};
+
+use crate::input::InputAction;
+use crate::replay::{Recorder, Recording};
+
+// stop trying to catch up after this many ticks in one go,
+// otherwise a slow tick makes the next frame even slower
+const MAX_CATCH_UP: u32 = 5;
+// turn based ticks until nothing changes, but not forever
+const MAX_SETTLE: u32 = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LoopMode {
+    RealTime,  // ticks keep happening without input
+    TurnBased, // wait for input, then tick until things settle
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct LoopConfig {
+    pub tick_rate: u32, // logic ticks per second
+    pub frame_cap: u32, // most frames drawn per second
+    pub mode: LoopMode,
+}
+
+impl LoopConfig {
+    pub fn new(tick_rate: u32, frame_cap: u32, mode: LoopMode) -> LoopConfig {
+        LoopConfig {
+            tick_rate,
+            frame_cap,
+            mode,
+        }
+    }
+}
+
+// What the loop drives
+pub trait Game {
+    fn running(&self) -> bool;
+    fn action(&self, event: Event) -> Option<InputAction>; // None for keys that do nothing
+    fn handle_action(&mut self, action: InputAction);
+    fn tick(&mut self) -> bool; // true if anything changed
+    fn draw(&mut self) -> Result<()>;
+}
+
+pub struct GameLoop {
+    pub config: LoopConfig,
+    pub ticks: u64,
+    pub frames: u64,
+    pub recorder: Option<Recorder>, // actions go in here on the way to the game
+}
+
+impl GameLoop {
+    pub fn new(config: LoopConfig) -> GameLoop {
+        GameLoop {
+            config,
+            ticks: 0,
+            frames: 0,
+            recorder: None,
+        }
+    }
+
+    pub fn record(&mut self, recorder: Recorder) {
+        self.recorder = Some(recorder);
+    }
+
+    pub fn run<G: Game>(&mut self, game: &mut G) -> Result<()> {
+        match self.config.mode {
+            LoopMode::RealTime => self.run_real_time(game),
+            LoopMode::TurnBased => self.run_turn_based(game),
+        }
+    }
+
+    fn run_real_time<G: Game>(&mut self, game: &mut G) -> Result<()> {
+        let tick_length = Duration::from_secs_f64(1.0 / self.config.tick_rate.max(1) as f64);
+        let frame_length = Duration::from_secs_f64(1.0 / self.config.frame_cap.max(1) as f64);
+
+        let mut accumulator = Duration::from_secs(0);
+        let mut last_update = Instant::now();
+        let mut last_draw = Instant::now();
+        let mut dirty = false;
+
+        game.draw()?;
+        self.frames += 1;
+
+        while game.running() {
+            // sleep in poll until input, the next tick or the next frame
+            let mut timeout = tick_length.saturating_sub(accumulator + last_update.elapsed());
+            if dirty {
+                timeout = timeout.min(frame_length.saturating_sub(last_draw.elapsed()));
+            }
+
+            if poll(timeout)? {
+                self.handle_event(game, read()?);
+                dirty = true;
+            }
+
+            let now = Instant::now();
+            accumulator += now - last_update;
+            last_update = now;
+
+            let mut steps = 0;
+            while accumulator >= tick_length {
+                if steps == MAX_CATCH_UP {
+                    accumulator = Duration::from_secs(0);
+                    break;
+                }
+                if game.tick() {
+                    dirty = true;
+                }
+                self.count_tick();
+                accumulator -= tick_length;
+                steps += 1;
+            }
+
+            if dirty && last_draw.elapsed() >= frame_length {
+                game.draw()?;
+                self.frames += 1;
+                last_draw = Instant::now();
+                dirty = false;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn run_turn_based<G: Game>(&mut self, game: &mut G) -> Result<()> {
+        game.draw()?;
+        self.frames += 1;
+
+        while game.running() {
+            self.handle_event(game, read()?);
+
+            for _ in 0..MAX_SETTLE {
+                self.count_tick();
+                if !game.tick() {
+                    break;
+                }
+            }
+
+            game.draw()?;
+            self.frames += 1;
+        }
+
+        Ok(())
+    }
+
+    // Feeds a recording back in, no terminal and no waiting. Actions go in
+    // at the tick they came in on, so the game ticks the same either way
+    pub fn replay<G: Game>(&mut self, game: &mut G, recording: &Recording) -> Result<()> {
+        let mut inputs = recording.inputs.iter().peekable();
+
+        game.draw()?;
+        self.frames += 1;
+
+        while game.running() {
+            while let Some((_, action)) = inputs.next_if(|(tick, _)| *tick <= self.ticks) {
+                game.handle_action(*action);
+            }
+            if inputs.peek().is_none() && self.ticks >= recording.end_tick {
+                break;
+            }
+
+            game.tick();
+            self.ticks += 1;
+
+            game.draw()?;
+            self.frames += 1;
+        }
+
+        Ok(())
+    }
+
+    // Actions are recorded rather than keys so replays don't depend on the
+    // keymap. Recorded before the game sees it, in case that's what crashes
+    fn handle_event<G: Game>(&mut self, game: &mut G, event: Event) {
+        let action = match game.action(event) {
+            Some(action) => action,
+            None => return,
+        };
+
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(self.ticks, action);
+        }
+        game.handle_action(action);
+    }
+
+    fn count_tick(&mut self) {
+        self.ticks += 1;
+        if let Some(recorder) = &mut self.recorder {
+            recorder.set_tick(self.ticks);
+        }
+    }
+}
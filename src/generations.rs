@@ -1,4 +1,8 @@
-#[derive(Copy, Clone, Debug)]
+use std::io::Result;
+
+use crate::save::{Persist, SaveReader, SaveWriter};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct GenerationalIndex {
     pub index: usize,
     pub generation: u64,
@@ -10,14 +14,14 @@ impl GenerationalIndex {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct AllocatorEntry {
     is_live: bool,
     pub generation: u64,
 }
 
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct GenerationalIndexAllocator {
     entries: Vec<AllocatorEntry>,
     free: Vec<usize>,
@@ -44,22 +48,6 @@ impl GenerationalIndexAllocator {
         &self.entries
     }
 
-    pub fn get_vec(&self) -> Vec<GenerationalIndex> {
-        let mut result: Vec<GenerationalIndex> = Vec::new();
-
-        for (index, generation) in self.entries.iter().enumerate() {
-            let gen_index = GenerationalIndex {
-                index,
-                generation: generation.generation,
-            };
-            if generation.is_live {
-                result.push(gen_index);
-            }
-        }
-
-        return result;
-    }
-
     pub fn allocate(&mut self) -> GenerationalIndex {
         if self.free.is_empty() {
             self.free.push(self.entries.len() as usize);
@@ -86,7 +74,10 @@ impl GenerationalIndexAllocator {
     }
 
     pub fn deallocate(&mut self, index: GenerationalIndex) -> bool {
-        let mut allocator = self.entries[index.index];
+        if index.index >= self.entries.len() {
+            return false;
+        }
+        let allocator = &mut self.entries[index.index];
 
         if allocator.is_live && allocator.generation == index.generation {
             self.free.push(index.index);
@@ -98,23 +89,26 @@ impl GenerationalIndexAllocator {
     }
 
     pub fn is_live(&self, index: GenerationalIndex) -> bool {
+        if index.index >= self.entries.len() {
+            return false;
+        }
         let entry = self.entries[index.index];
 
-        return entry.is_live;
+        // old index to a reused slot is not live
+        entry.is_live && entry.generation == index.generation
     }
 }
 
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 struct ArrayEntry<T> {
     value: T,
     generation: u64,
 }
 
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct GenerationalIndexArray<T>(Vec<Option<ArrayEntry<T>>>);
-// need to build iter for T
 
 impl<T> GenerationalIndexArray<T> {
     pub fn new() -> GenerationalIndexArray<T> {
@@ -180,4 +174,240 @@ impl<T> GenerationalIndexArray<T> {
 
         return None;
     }
-}
\ No newline at end of file
+
+    // only removes if the generation matches
+    pub fn remove(&mut self, index: GenerationalIndex) -> Option<T> {
+        if index.index >= self.0.len() {
+            return None;
+        }
+
+        let matches = match &self.0[index.index] {
+            Some(entry) => entry.generation == index.generation,
+            None => false,
+        };
+
+        if matches {
+            return self.0[index.index].take().map(|entry| entry.value);
+        }
+
+        None
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (GenerationalIndex, &T)> {
+        self.0
+            .iter()
+            .enumerate()
+            .filter_map(|(index, entry)| {
+                entry.as_ref().map(|entry| (GenerationalIndex { index, generation: entry.generation }, &entry.value))
+            })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (GenerationalIndex, &mut T)> {
+        self.0
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(index, entry)| match entry {
+                Some(entry) => Some((GenerationalIndex { index, generation: entry.generation }, &mut entry.value)),
+                None => None,
+            })
+    }
+}
+
+// Joins, walk two or three storages side by side and only yield
+// entities that have all of the components, same generation in each
+
+pub fn join<'a, A, B>(
+    a: &'a GenerationalIndexArray<A>,
+    b: &'a GenerationalIndexArray<B>,
+) -> impl Iterator<Item = (GenerationalIndex, &'a A, &'a B)> {
+    a.0.iter()
+        .zip(b.0.iter())
+        .enumerate()
+        .filter_map(|(index, entries)| match entries {
+            (Some(a), Some(b)) if a.generation == b.generation => {
+                Some((GenerationalIndex { index, generation: a.generation }, &a.value, &b.value))
+            },
+            _ => None,
+        })
+}
+
+pub fn join_mut<'a, A, B>(
+    a: &'a GenerationalIndexArray<A>,
+    b: &'a mut GenerationalIndexArray<B>,
+) -> impl Iterator<Item = (GenerationalIndex, &'a A, &'a mut B)> {
+    a.0.iter()
+        .zip(b.0.iter_mut())
+        .enumerate()
+        .filter_map(|(index, entries)| match entries {
+            (Some(a), Some(b)) if a.generation == b.generation => {
+                Some((GenerationalIndex { index, generation: a.generation }, &a.value, &mut b.value))
+            },
+            _ => None,
+        })
+}
+
+pub fn join_both_mut<'a, A, B>(
+    a: &'a mut GenerationalIndexArray<A>,
+    b: &'a mut GenerationalIndexArray<B>,
+) -> impl Iterator<Item = (GenerationalIndex, &'a mut A, &'a mut B)> {
+    a.0.iter_mut()
+        .zip(b.0.iter_mut())
+        .enumerate()
+        .filter_map(|(index, entries)| match entries {
+            (Some(a), Some(b)) if a.generation == b.generation => {
+                Some((GenerationalIndex { index, generation: a.generation }, &mut a.value, &mut b.value))
+            },
+            _ => None,
+        })
+}
+
+pub fn join3_mut<'a, A, B, C>(
+    a: &'a GenerationalIndexArray<A>,
+    b: &'a GenerationalIndexArray<B>,
+    c: &'a mut GenerationalIndexArray<C>,
+) -> impl Iterator<Item = (GenerationalIndex, &'a A, &'a B, &'a mut C)> {
+    a.0.iter()
+        .zip(b.0.iter())
+        .zip(c.0.iter_mut())
+        .enumerate()
+        .filter_map(|(index, entries)| match entries {
+            ((Some(a), Some(b)), Some(c)) if a.generation == b.generation && b.generation == c.generation => {
+                Some((GenerationalIndex { index, generation: a.generation }, &a.value, &b.value, &mut c.value))
+            },
+            _ => None,
+        })
+}
+
+impl Persist for GenerationalIndex {
+    fn save(&self, out: &mut SaveWriter) {
+        out.write(&self.index);
+        out.write(&self.generation);
+    }
+
+    fn load(input: &mut SaveReader) -> Result<Self> {
+        Ok(GenerationalIndex {
+            index: input.read()?,
+            generation: input.read()?,
+        })
+    }
+}
+
+impl Persist for AllocatorEntry {
+    fn save(&self, out: &mut SaveWriter) {
+        out.write(&self.is_live);
+        out.write(&self.generation);
+    }
+
+    fn load(input: &mut SaveReader) -> Result<Self> {
+        Ok(AllocatorEntry {
+            is_live: input.read()?,
+            generation: input.read()?,
+        })
+    }
+}
+
+// free list order matters, it decides which slot is handed out next
+impl Persist for GenerationalIndexAllocator {
+    fn save(&self, out: &mut SaveWriter) {
+        out.write(&self.entries);
+        out.write(&self.free);
+    }
+
+    fn load(input: &mut SaveReader) -> Result<Self> {
+        Ok(GenerationalIndexAllocator {
+            entries: input.read()?,
+            free: input.read()?,
+        })
+    }
+}
+
+impl<T: Persist> Persist for ArrayEntry<T> {
+    fn save(&self, out: &mut SaveWriter) {
+        out.write(&self.generation);
+        out.write(&self.value);
+    }
+
+    fn load(input: &mut SaveReader) -> Result<Self> {
+        let generation = input.read()?;
+
+        Ok(ArrayEntry {
+            value: input.read()?,
+            generation,
+        })
+    }
+}
+
+impl<T: Persist> Persist for GenerationalIndexArray<T> {
+    fn save(&self, out: &mut SaveWriter) {
+        out.write(&self.0);
+    }
+
+    fn load(input: &mut SaveReader) -> Result<Self> {
+        Ok(GenerationalIndexArray(input.read()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // three entities, the middle one dies and its slot is handed out again
+    fn allocated() -> (GenerationalIndexAllocator, Vec<GenerationalIndex>) {
+        let mut allocator = GenerationalIndexAllocator::new(1);
+        let a = allocator.allocate();
+        let b = allocator.allocate();
+        let c = allocator.allocate();
+        allocator.deallocate(b);
+        let d = allocator.allocate();
+
+        (allocator, vec![a, b, c, d])
+    }
+
+    #[test]
+    fn join_mut_changes_only_entities_with_both() {
+        let (_, entities) = allocated();
+        let (a, b, c, d) = (entities[0], entities[1], entities[2], entities[3]);
+        let mut names: GenerationalIndexArray<&str> = GenerationalIndexArray::new();
+        let mut counts: GenerationalIndexArray<u32> = GenerationalIndexArray::new();
+        names.set(a, "a");
+        names.set(b, "b"); // dead, d has its slot now
+        names.set(c, "c");
+        counts.set(a, 1);
+        counts.set(d, 2);
+
+        for (_, name, count) in join_mut(&names, &mut counts) {
+            *count += name.len() as u32 * 10;
+        }
+
+        assert_eq!(counts.get(a), Some(&11));
+        assert_eq!(counts.get(d), Some(&2));
+        assert_eq!(counts.get(c), None);
+    }
+
+    #[test]
+    fn join3_mut_needs_all_three() {
+        let (_, entities) = allocated();
+        let (a, c, d) = (entities[0], entities[2], entities[3]);
+        let mut speeds: GenerationalIndexArray<u32> = GenerationalIndexArray::new();
+        let mut costs: GenerationalIndexArray<u32> = GenerationalIndexArray::new();
+        let mut spent: GenerationalIndexArray<u32> = GenerationalIndexArray::new();
+        for entity in [a, c, d].iter() {
+            speeds.set(*entity, 2);
+            spent.set(*entity, 0);
+        }
+        costs.set(a, 5);
+        costs.set(d, 7);
+
+        let joined: Vec<GenerationalIndex> = join3_mut(&speeds, &costs, &mut spent)
+            .map(|(entity, speed, cost, spent)| {
+                *spent += speed * cost;
+                entity
+            })
+            .collect();
+
+        assert_eq!(joined, vec![a, d]);
+        assert_eq!(spent.get(a), Some(&10));
+        assert_eq!(spent.get(c), Some(&0));
+        assert_eq!(spent.get(d), Some(&14));
+    }
+}
@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::Result;
+
+use crossterm::event::KeyCode;
+
+use crate::components::Direction;
+use crate::debug_overlay::DebugOverlay;
+use crate::save::{corrupt, Persist, SaveReader, SaveWriter};
+
+// What a key means, the game only ever sees these.
+// Not Action, that one is what the AI decides to do
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputAction {
+    Move(Direction),
+    Wait,
+    Interact,
+    Quit,
+    OpenMenu,
+    ToggleOverlay(DebugOverlay),
+    ScrollLogUp,
+    ScrollLogDown,
+}
+
+impl InputAction {
+    // these wait for the players turn, the rest happen straight away
+    pub fn takes_turn(&self) -> bool {
+        matches!(self, InputAction::Move(_) | InputAction::Wait | InputAction::Interact)
+    }
+
+    // the names used in keymap files and recordings
+    pub fn from_name(name: &str) -> Option<InputAction> {
+        ACTION_NAMES.iter().find(|(n, _)| *n == name).map(|(_, action)| *action)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match ACTION_NAMES.iter().find(|(_, action)| action == self) {
+            Some((name, _)) => name,
+            None => unreachable!("every action has a name"),
+        }
+    }
+}
+
+const ACTION_NAMES: [(&str, InputAction); 18] = [
+    ("move_up", InputAction::Move(Direction::Up)),
+    ("move_down", InputAction::Move(Direction::Down)),
+    ("move_left", InputAction::Move(Direction::Left)),
+    ("move_right", InputAction::Move(Direction::Right)),
+    ("move_up_left", InputAction::Move(Direction::UpLeft)),
+    ("move_up_right", InputAction::Move(Direction::UpRight)),
+    ("move_down_left", InputAction::Move(Direction::DownLeft)),
+    ("move_down_right", InputAction::Move(Direction::DownRight)),
+    ("wait", InputAction::Wait),
+    ("interact", InputAction::Interact),
+    ("quit", InputAction::Quit),
+    ("menu", InputAction::OpenMenu),
+    ("overlay_collision", InputAction::ToggleOverlay(DebugOverlay::Collision)),
+    ("overlay_dijkstra", InputAction::ToggleOverlay(DebugOverlay::Dijkstra)),
+    ("overlay_ids", InputAction::ToggleOverlay(DebugOverlay::EntityIds)),
+    ("overlay_intents", InputAction::ToggleOverlay(DebugOverlay::AiIntents)),
+    ("log_up", InputAction::ScrollLogUp),
+    ("log_down", InputAction::ScrollLogDown),
+];
+
+// by name, so a recording still reads if the enum is reordered
+impl Persist for InputAction {
+    fn save(&self, out: &mut SaveWriter) {
+        out.write(&self.name().to_string());
+    }
+
+    fn load(input: &mut SaveReader) -> Result<Self> {
+        let name: String = input.read()?;
+
+        match InputAction::from_name(&name) {
+            Some(action) => Ok(action),
+            None => Err(corrupt(&format!("unknown action {}", name))),
+        }
+    }
+}
+
+// Keys to actions. Modifiers are ignored, a shifted letter
+// already comes through as the upper case char
+pub struct Keymap {
+    bindings: HashMap<KeyCode, InputAction>,
+}
+
+impl Keymap {
+    // Esc always opens the menu, so a broken keymap can still quit
+    pub fn new() -> Keymap {
+        let mut keymap = Keymap {
+            bindings: HashMap::new(),
+        };
+        keymap.bind(KeyCode::Esc, InputAction::OpenMenu);
+        keymap
+    }
+
+    // arrows, vi-keys and numpad all at once, like before keymaps
+    pub fn default_keys() -> Keymap {
+        let mut keymap = Keymap::new();
+        let moves = [
+            (Direction::Left, [KeyCode::Left, KeyCode::Char('h'), KeyCode::Char('4')]),
+            (Direction::Down, [KeyCode::Down, KeyCode::Char('j'), KeyCode::Char('2')]),
+            (Direction::Up, [KeyCode::Up, KeyCode::Char('k'), KeyCode::Char('8')]),
+            (Direction::Right, [KeyCode::Right, KeyCode::Char('l'), KeyCode::Char('6')]),
+        ];
+        for (direction, keys) in moves.iter() {
+            for key in keys.iter() {
+                keymap.bind(*key, InputAction::Move(*direction));
+            }
+        }
+        let diagonals = [
+            (Direction::UpLeft, [KeyCode::Char('y'), KeyCode::Char('7')]),
+            (Direction::UpRight, [KeyCode::Char('u'), KeyCode::Char('9')]),
+            (Direction::DownLeft, [KeyCode::Char('b'), KeyCode::Char('1')]),
+            (Direction::DownRight, [KeyCode::Char('n'), KeyCode::Char('3')]),
+        ];
+        for (direction, keys) in diagonals.iter() {
+            for key in keys.iter() {
+                keymap.bind(*key, InputAction::Move(*direction));
+            }
+        }
+
+        keymap.bind(KeyCode::Char('.'), InputAction::Wait);
+        keymap.bind(KeyCode::Char('5'), InputAction::Wait);
+        keymap.bind(KeyCode::Char('e'), InputAction::Interact);
+        keymap.bind(KeyCode::Enter, InputAction::Interact);
+        keymap.bind(KeyCode::Char('q'), InputAction::Quit);
+        // F1 to F4, in the order of DebugOverlay::ALL
+        for (i, overlay) in DebugOverlay::ALL.iter().enumerate() {
+            keymap.bind(KeyCode::F(i as u8 + 1), InputAction::ToggleOverlay(*overlay));
+        }
+        keymap.bind(KeyCode::PageUp, InputAction::ScrollLogUp);
+        keymap.bind(KeyCode::PageDown, InputAction::ScrollLogDown);
+        keymap
+    }
+
+    // One binding a line, key = action, # for comments:
+    //   w = move_up
+    //   space = wait
+    pub fn from_file(path: &str) -> Result<Keymap> {
+        Keymap::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Keymap> {
+        let mut keymap = Keymap::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = match line.find('#') {
+                Some(comment) => &line[..comment],
+                None => line,
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let mut parts = line.splitn(2, '=');
+            let (key, action) = match (parts.next(), parts.next()) {
+                (Some(key), Some(action)) => (key.trim(), action.trim()),
+                _ => return Err(corrupt(&format!("keymap line {}: expected key = action", number + 1))),
+            };
+            let key = match key_from_name(key) {
+                Some(key) => key,
+                None => return Err(corrupt(&format!("keymap line {}: unknown key {}", number + 1, key))),
+            };
+            let action = match InputAction::from_name(action) {
+                Some(action) => action,
+                None => return Err(corrupt(&format!("keymap line {}: unknown action {}", number + 1, action))),
+            };
+            keymap.bind(key, action);
+        }
+
+        Ok(keymap)
+    }
+
+    // a key does one thing, binding it again replaces it
+    pub fn bind(&mut self, key: KeyCode, action: InputAction) {
+        self.bindings.insert(key, action);
+    }
+
+    pub fn action(&self, key: KeyCode) -> Option<InputAction> {
+        self.bindings.get(&key).copied()
+    }
+}
+
+// a single character is that key, '#' and '=' can't be bound
+fn key_from_name(name: &str) -> Option<KeyCode> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(KeyCode::Char(c));
+    }
+
+    match name.to_lowercase().as_str() {
+        "space" => Some(KeyCode::Char(' ')),
+        "left" => Some(KeyCode::Left),
+        "right" => Some(KeyCode::Right),
+        "up" => Some(KeyCode::Up),
+        "down" => Some(KeyCode::Down),
+        "enter" => Some(KeyCode::Enter),
+        "esc" => Some(KeyCode::Esc),
+        "tab" => Some(KeyCode::Tab),
+        "backspace" => Some(KeyCode::Backspace),
+        "home" => Some(KeyCode::Home),
+        "end" => Some(KeyCode::End),
+        "page_up" => Some(KeyCode::PageUp),
+        "page_down" => Some(KeyCode::PageDown),
+        "delete" => Some(KeyCode::Delete),
+        "insert" => Some(KeyCode::Insert),
+        name => match name.strip_prefix('f').map(|n| n.parse::<u8>()) {
+            Some(Ok(n)) if (1..=12).contains(&n) => Some(KeyCode::F(n)),
+            _ => None,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_go_both_ways() {
+        for (name, action) in ACTION_NAMES.iter() {
+            assert_eq!(action.name(), *name);
+            assert_eq!(InputAction::from_name(name), Some(*action));
+        }
+    }
+
+    #[test]
+    fn shipped_keymaps_load() {
+        for path in ["keymaps/vi.txt", "keymaps/wasd.txt", "keymaps/numpad.txt"].iter() {
+            assert!(Keymap::from_file(path).is_ok(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn bad_keymap_lines_are_errors() {
+        assert!(Keymap::parse("w = fly").is_err());
+        assert!(Keymap::parse("ww = wait").is_err());
+        assert!(Keymap::parse("wait").is_err());
+
+        let keymap = Keymap::parse("space = wait # comment\nf3 = overlay_ids").unwrap();
+        assert_eq!(keymap.action(KeyCode::Char(' ')), Some(InputAction::Wait));
+        assert_eq!(keymap.action(KeyCode::F(3)), Some(InputAction::ToggleOverlay(DebugOverlay::EntityIds)));
+        assert_eq!(keymap.action(KeyCode::Esc), Some(InputAction::OpenMenu));
+    }
+}
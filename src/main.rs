@@ -1,16 +1,16 @@
-use std::time::Duration;
-//use std::io::{stdout, Write};
+use std::collections::HashMap;
 
 use crossterm::{
     style::{Color, Stylize}, 
     Result,
-    event::{read, Event, poll, KeyCode},
+    event::Event,
 };
 
 mod generations;
 use crate::generations::*;
 mod renderer;
 use crate::renderer::*;
+mod backend;
 mod components;
 use crate::components::*;
 mod enemy_ai;
@@ -19,15 +19,43 @@ mod map_generation;
 use crate::map_generation::*;
 mod dijkstra_maps;
 use crate::dijkstra_maps::*;
-
-type EntityMap<T> = generations::GenerationalIndexArray<T>;
-pub type Entity = generations::GenerationalIndex;
+mod game_loop;
+use crate::game_loop::*;
+mod scheduler;
+use crate::scheduler::*;
+mod world;
+use crate::world::*;
+mod schedule;
+use crate::schedule::*;
+mod tilemap;
+use crate::tilemap::*;
+mod fov;
+use crate::fov::*;
+mod a_star;
+mod point;
+mod save;
+use crate::save::*;
+mod rng;
+use crate::rng::*;
+mod spatial;
+use crate::spatial::*;
+mod combat;
+use crate::combat::*;
+mod message_log;
+use crate::message_log::*;
+mod ui;
+use crate::ui::*;
+mod replay;
+use crate::replay::*;
+mod input;
+use crate::input::*;
+mod debug_overlay;
+use crate::debug_overlay::*;
 
 /*
 Notes: 
-after adding map, for blindness add high layer of "blind"
-then use collision buffer and local to remove parts,
-thus making it clear and visible.
+blindness is done, see fov.rs, tiles out of view are
+drawn dimmed and entities out of view are not drawn
 
 ok move working, now fields things that arent collider but visible
 
@@ -50,7 +78,126 @@ fn handle_comps(&mut self) {
 */
 
 fn main() -> Result<()> {
-    let style_map: StyleMap = vec![ // 1 for character, 2 for wall, 0 for floor
+    let args: Vec<String> = std::env::args().collect();
+
+    // --seed 1234 to play the same game again
+    let seed: Option<u64> = match arg_value(&args, "--seed")? {
+        Some(seed) => match seed.parse() {
+            Ok(seed) => Some(seed),
+            Err(_) => return Err(bad_argument("--seed needs a number")),
+        },
+        None => None,
+    };
+
+    // --record keys.txt saves every key pressed, --replay keys.txt
+    // plays them back without a terminal, eg. to find a crash
+    let record_path = arg_value(&args, "--record")?;
+    let recording = match arg_value(&args, "--replay")? {
+        Some(path) => Some(Recording::from_file(&path)?),
+        None => None,
+    };
+    let seed = match &recording {
+        Some(recording) => Some(recording.seed),
+        None => seed,
+    };
+    // --keys keymaps/wasd.txt
+    let keymap = match arg_value(&args, "--keys")? {
+        Some(path) => Some(Keymap::from_file(&path)?),
+        None => None,
+    };
+    // --overlay dijkstra --overlay ids, F1 to F4 switch them in game
+    let mut debug_overlays: Vec<DebugOverlay> = Vec::new();
+    for name in arg_values(&args, "--overlay")? {
+        match DebugOverlay::from_name(&name) {
+            Some(overlay) => debug_overlays.push(overlay),
+            None => return Err(bad_argument(&format!("no overlay called {}", name))),
+        }
+    }
+    // --turn-based, nothing happens until a key is pressed
+    let loop_mode = match args.iter().any(|arg| arg == "--turn-based") {
+        true => LoopMode::TurnBased,
+        false => LoopMode::RealTime,
+    };
+    // --diagonal, numpad and yubn move diagonally, for everyone
+    let diagonal_movement = args.iter().any(|arg| arg == "--diagonal");
+
+    let dimensions: Dimemsion = (200, 100);
+    let view_port: Dimemsion = (150, 60);
+
+    let mut game: GameState = match recording {
+        Some(_) => GameState::new_headless(dimensions, view_port, style_map()),
+        None => GameState::new(dimensions, view_port, style_map()),
+    };
+
+    let random_map = true;
+    let fog_of_war = true;
+    let log_rows = 5; // added under the map, PageUp/PageDown to scroll
+    let loop_config = LoopConfig::new(20, 60, loop_mode);
+
+    // a save from last time carries on where it left off,
+    // recordings always start a new game, the actions only fit that one
+    let fresh = record_path.is_some() || recording.is_some();
+    let resume = !fresh && std::path::Path::new(SAVE_PATH).exists();
+
+    if resume {
+        if let Err(err) = game.load_from(SAVE_PATH) {
+            game.renderer.reset_term()?;
+            return Err(err);
+        }
+        game.log.info("Welcome back.");
+    } else {
+        if let Some(seed) = seed {
+            game.rng = GameRng::new(seed);
+        }
+
+        game.init_test_level(random_map);
+        game.fov.enabled = fog_of_war;
+        game.log.info("Welcome! Arrows to move, Esc for the menu, q to quit.");
+        game.log.info(&format!("Seed {}", game.rng.seed()));
+    }
+    // a save keeps the setting, so this only ever turns it on
+    if diagonal_movement {
+        game.set_movement(Connectivity::Eight);
+    }
+    if let Some(keymap) = keymap {
+        game.keymap = keymap;
+    }
+    game.renderer.set_panel_rows(log_rows)?;
+    for overlay in debug_overlays.iter() {
+        game.schedule.set_enabled(overlay.system_name(), true);
+    }
+
+    let mut game_loop = GameLoop::new(loop_config);
+    if let Some(recording) = recording {
+        game_loop.replay(&mut game, &recording)?;
+        println!("replayed {} actions over {} ticks", recording.inputs.len(), game_loop.ticks);
+        println!("player alive: {}", game.player.is_some());
+        return Ok(());
+    }
+    if let Some(path) = record_path {
+        game_loop.record(Recorder::new(game.rng.seed(), &path));
+    }
+    game_loop.run(&mut game)?;
+
+    game.renderer.reset_term()?;
+
+    // a recorded game was a fresh one, the players real save is left alone
+    if fresh {
+        return Ok(());
+    }
+    // quitting keeps the game for next time, dying doesn't
+    if game.player.is_some() {
+        game.save_to(SAVE_PATH)?;
+    } else if resume {
+        std::fs::remove_file(SAVE_PATH)?;
+    }
+
+    Ok(())
+}
+
+// what each glyph in the buffers looks like
+fn style_map() -> StyleMap {
+    vec![ // 1 for character, 2 for wall, 0 for floor
         '^'.on(Color::Red),  // test
         ' '.on(Color::Grey), // 1
         '@'.on(Color::Grey), // 2
@@ -85,102 +232,76 @@ fn main() -> Result<()> {
         ' '.on(Color::AnsiValue(233)), // 31
         ' '.on(Color::AnsiValue(232)), // 32
         ' '.on(Color::AnsiValue(231)), // 33
-    ];
-
-    let dimensions: Dimemsion = (200, 100);
-    let view_port: Dimemsion = (150, 60);
-
-    let mut game: GameState = GameState::new(dimensions, view_port, style_map);
-
-    let random_map = true;
-    let test_collison = false;
-    let test_dijk = true;
+        ' '.on(Color::AnsiValue(238)), // 34 remembered floor
+        '#'.on(Color::AnsiValue(238)), // 35 remembered wall
+        ' '.on(Color::Black), // 36 unseen
+        '+'.on(Color::Grey), // 37 closed door
+        '\''.on(Color::Grey), // 38 open door
+        '%'.on(Color::Grey), // 39 crate
+    ]
+}
 
-    game.init_player((5, 5));
-    game.init_test_enemy((22, 18));
-    game.init_test_enemy((40, 40));
-    game.init_test_enemy((22, 15));
+const SAVE_PATH: &str = "savegame.txt";
 
-    if random_map {
-        game.init_map(0.45); // 0.5 is good
-    } else {
-        game.init_borders();
-        game.init_background();
-        game.init_field();
-    }
-
-    while game.running {
-        if is_event_availble()? {
-            game.handle_input(read()?);
-        }
-
-        game.handle_collision();
-        game.handle_movement();
-        game.test_influences();
-        game.handle_enemy_energy_move();
-        game.handle_render();
-        game.renderer.render()?;
-        
+// the argument after flag, None if flag isn't there
+fn arg_value(args: &[String], flag: &str) -> std::io::Result<Option<String>> {
+    match args.iter().position(|arg| arg == flag) {
+        Some(i) => match args.get(i + 1) {
+            Some(value) => Ok(Some(value.clone())),
+            None => Err(bad_argument(&format!("{} needs a value", flag))),
+        },
+        None => Ok(None),
     }
+}
 
-    if test_collison {    
-        game.running = true;
+// every argument after flag, it can be given more than once
+fn arg_values(args: &[String], flag: &str) -> std::io::Result<Vec<String>> {
+    let mut values = Vec::new();
 
-        while game.running {
-            if is_event_availble()? {
-                game.handle_input(read()?);
+    for (i, arg) in args.iter().enumerate() {
+        if arg == flag {
+            match args.get(i + 1) {
+                Some(value) => values.push(value.clone()),
+                None => return Err(bad_argument(&format!("{} needs a value", flag))),
             }
-
-            game.handle_collision();
-
-            game.renderer.insert_matrix((0, 0), &game.collision_buffer);
-            game.renderer.render()?;
         }
     }
 
-    if test_dijk{
-        game.running =  true;
-
-        game.test_influences();
-
-        while game.running {
-            if is_event_availble()? {
-                game.test_influences();
-                game.handle_input(read()?);
-            }
-
-            game.handle_collision();
-            game.handle_movement();
-
-            
-            //game.renderer.
-            game.renderer.insert_matrix((0, 0), &game.player_dijk.make_render());
-            game.renderer.render()?;
-        }
-    }
-
-    Renderer::reset_term()?;
+    Ok(values)
+}
 
-    Ok(())
+fn bad_argument(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, message.to_string())
 }
 
 struct GameState {
     // resources
     renderer: Renderer,
     collision_buffer: Buffer,
+    spatial_index: SpatialIndex, // who is where, rebuilt with the collision buffer
+    collision_events: Vec<CollisionEvent>, // bumps from this tick's movement
+    damage_events: Vec<DamageEvent>, // hits from this tick
+    log: MessageLog,
+    menu: Option<Menu>, // open menus take all the input
+    keymap: Keymap,
+    queued_action: Option<InputAction>, // waiting for the players turn
     player_dijk: DijkstraMap,
+    desire_maps: HashMap<Desire, DesireMap>,
     running: bool,
-    empty_buffer: Buffer,
+    dirty: bool, // something moved this tick
     map_generator: CaveMapGenerator,
+    tilemap: TileMap,
+    fov: FieldOfView,
+    turn_scheduler: TurnScheduler,
+    schedule: Schedule<GameState>,
+    movement: Connectivity, // Eight to allow diagonal steps
+    rng: GameRng, // everything random goes through this
 
     // ECS
-    entity_allocator: GenerationalIndexAllocator,
-    // for now assume every entity has render comp
-    render_components: EntityMap<RenderComponent>,
-    movement_components: EntityMap<MovementComponent>,
-    collision_components: EntityMap<CollisionComponent>,
-    energy_components: EntityMap<EnergyComponent>,
-    enemy_ai_components: EntityMap<EnemyAIComponent>,
+    world: World,
+
+    // entities to remove once the current systems are done
+    despawn_queue: Vec<Entity>,
 
     // Player
     player: Option<Entity>,
@@ -188,140 +309,438 @@ struct GameState {
 
 impl GameState {
     fn new(dimensions: Dimemsion, view_port: Dimemsion, style_map: StyleMap) -> GameState {
-        // resources
         let renderer =  match renderer::Renderer::initialize(dimensions, view_port, style_map) {
             Ok(r) => r,
             Err(err) => panic!("Failed Renderer Intialisation: {}", err),
         };
+
+        GameState::from_renderer(renderer)
+    }
+
+    // no terminal needed, frames end up in a HeadlessBackend
+    fn new_headless(dimensions: Dimemsion, view_port: Dimemsion, style_map: StyleMap) -> GameState {
+        let renderer =  match renderer::Renderer::headless(dimensions, view_port, style_map) {
+            Ok(r) => r,
+            Err(err) => panic!("Failed Renderer Intialisation: {}", err),
+        };
+
+        GameState::from_renderer(renderer)
+    }
+
+    fn from_renderer(renderer: Renderer) -> GameState {
+        // resources
+        let dimensions = renderer.dimensions;
         let collision_buffer = renderer.input_buffer.clone();
         let player_dijk = DijkstraMap::new(dimensions, Vec::new());
-        let empty_buffer = renderer.input_buffer.clone();
         let map_generator = CaveMapGenerator::new(dimensions);
+        let tilemap = TileMap::new(dimensions);
+        let fov = FieldOfView::new(dimensions, 30);
+        let turn_scheduler = TurnScheduler::new();
         
-        // ECS
-        let entity_allocator = generations::GenerationalIndexAllocator::new(1);
-        let render_components = EntityMap::<RenderComponent>::new();
-        let movement_components = EntityMap::<MovementComponent>::new();
-        let collision_components = EntityMap::<CollisionComponent>::new();
-        let energy_components = EntityMap::<EnergyComponent>::new();
-        let enemy_ai_components = EntityMap::<EnemyAIComponent>::new();
+        // ECS, systems borrow these so they have to exist
+        let mut world = World::new();
+        world.register::<RenderComponent>();
+        world.register::<MovementComponent>();
+        world.register::<CollisionComponent>();
+        world.register::<EnergyComponent>();
+        world.register::<EnemyAIComponent>();
+        world.register::<DoorComponent>();
+        world.register::<PushableComponent>();
+        world.register::<HealthComponent>();
+        world.register::<AttackComponent>();
+        world.register::<DefenseComponent>();
+        world.register::<FactionComponent>();
         
         GameState {
             renderer,
             collision_buffer,
+            spatial_index: SpatialIndex::new(),
+            collision_events: Vec::new(),
+            damage_events: Vec::new(),
+            log: MessageLog::new(200),
+            menu: None,
+            keymap: Keymap::default_keys(),
+            queued_action: None,
             player_dijk,
+            desire_maps: HashMap::new(),
             running: true,
-            empty_buffer,
+            dirty: false,
             map_generator,
-            entity_allocator,
-            render_components,
-            movement_components,
-            collision_components,
-            energy_components,
-            enemy_ai_components,
+            tilemap,
+            fov,
+            turn_scheduler,
+            schedule: GameState::default_schedule(),
+            movement: Connectivity::Four,
+            rng: GameRng::from_entropy(),
+            world,
+            despawn_queue: Vec::new(),
             player: None,
         }
     }
 
+    fn default_schedule() -> Schedule<GameState> {
+        let mut schedule: Schedule<GameState> = Schedule::new();
+
+        schedule.add_system("player_input", Stage::PreUpdate, GameState::take_queued_action);
+        schedule.add_system("collision", Stage::Update, GameState::handle_collision);
+        schedule.add_system("movement", Stage::Update, |game| { game.handle_movement(); })
+            .after("collision");
+        schedule.add_system("bumps", Stage::Update, GameState::handle_bumps)
+            .after("movement");
+        schedule.add_system("influences", Stage::Update, GameState::test_influences)
+            .after("bumps");
+        schedule.add_system("enemy_turns", Stage::Update, GameState::handle_enemy_energy_move)
+            .after("influences");
+        schedule.add_system("deaths", Stage::PostUpdate, GameState::handle_deaths);
+        schedule.add_system("despawns", Stage::PostUpdate, GameState::flush_despawns)
+            .after("deaths");
+
+        schedule.add_system("fov", Stage::Render, GameState::handle_fov)
+            .before("render");
+        schedule.add_system("render", Stage::Render, GameState::handle_render);
+        schedule.add_system("message_log", Stage::Render, GameState::draw_message_log)
+            .after("render");
+        schedule.add_system("hud", Stage::Render, GameState::draw_hud)
+            .after("render");
+
+        // off unless asked for. Labels first, overlays only fill
+        // empty cells so the heatmaps would cover them otherwise
+        schedule.add_system(DebugOverlay::EntityIds.system_name(), Stage::Debug, GameState::draw_entity_id_debug)
+            .disabled();
+        schedule.add_system(DebugOverlay::AiIntents.system_name(), Stage::Debug, GameState::draw_ai_intent_debug)
+            .disabled()
+            .after(DebugOverlay::EntityIds.system_name());
+        schedule.add_system(DebugOverlay::Collision.system_name(), Stage::Debug, GameState::draw_collision_debug)
+            .disabled()
+            .after(DebugOverlay::AiIntents.system_name());
+        schedule.add_system(DebugOverlay::Dijkstra.system_name(), Stage::Debug, GameState::draw_dijkstra_debug)
+            .disabled()
+            .after(DebugOverlay::Collision.system_name());
+
+        schedule
+    }
+
+    // by index, the schedule keeps its order between frames
+    fn run_stage(&mut self, stage: Stage) {
+        for i in 0..self.schedule.stage_len(stage) {
+            if let Some(system) = self.schedule.system(stage, i) {
+                system(self);
+            }
+        }
+    }
 
     fn test_influences(&mut self) {
         let player = match self.player {
             Some(e) => e,
-            None => panic!("No player!"),
+            None => return,
         };
 
-        let comp = match self.render_components.get_mut(player) {
+        let comp = match self.world.get::<RenderComponent>(player) {
             Some(comp) => comp,
             None => panic!("No player!"),
         };
-        let col_influence: Influence = Influence {
-            position: (0, 0),
-            matrix: self.collision_buffer.clone(),
-            value: u32::MAX,
-        };
-        let player_influence: Influence = Influence {
-            position: comp.position_tl,
-            matrix: vec![vec![1]],
-            value: 0,
-        };
-        let _player_influence_2: Influence = Influence {
-            position: (comp.position_tl.0 + 5, comp.position_tl.1 + 5),
-            matrix: vec![
+        // anything in the way, plus the walls straight off the tilemap
+        let mut blocked = self.collision_buffer.clone();
+        // tiles slower to cross, same costs A* uses, one influence per cost
+        let mut slow: HashMap<u32, Buffer> = HashMap::new();
+        let size = (blocked.len(), blocked[0].len());
+        for (x, col) in blocked.iter_mut().enumerate() {
+            for (y, val) in col.iter_mut().enumerate() {
+                match self.tilemap.move_cost((x as u16, y as u16)) {
+                    None => *val = 1,
+                    Some(1) => (),
+                    Some(cost) => slow.entry(cost).or_insert_with(|| vec![vec![0; size.1]; size.0])[x][y] = 1,
+                }
+            }
+        }
+        let col_influence: Influence = Influence::value((0, 0), blocked, u32::MAX);
+        let player_influence: Influence = Influence::value(comp.position_tl, vec![vec![1]], 0);
+        let _player_influence_2: Influence = Influence::value(
+            (comp.position_tl.0 + 5, comp.position_tl.1 + 5),
+            vec![
                 vec![1;4],
             ],
-            value: 0,
-        };
+            0,
+        );
+        drop(comp);
         
         self.player_dijk.influences = vec![col_influence, player_influence];
+        for (cost, matrix) in slow {
+            self.player_dijk.influences.push(Influence::cost((0, 0), matrix, cost));
+        }
+        // thrown away by new_implementation anyway, kept to compare
+        let previous = std::mem::take(&mut self.player_dijk.current_generation);
         self.player_dijk.new_implementation();
+
+        // the flee map is slow to make, only redo it when
+        // the player or something in the way has moved
+        if self.player_dijk.current_generation == previous && self.desire_maps.contains_key(&Desire::FleePlayer) {
+            return;
+        }
+        self.desire_maps.insert(Desire::ChasePlayer, DesireMap::from_dijkstra(&self.player_dijk));
+        self.desire_maps.insert(Desire::FleePlayer, DesireMap::flee(&self.player_dijk, -1.2));
+    }
+
+    // input, AI and the player map all follow this
+    fn set_movement(&mut self, connectivity: Connectivity) {
+        self.movement = connectivity;
+        self.player_dijk.connectivity = connectivity;
+    }
+
+    // Everything needed to carry on exactly. Maps built from other
+    // state (collision, dijkstra, spatial index) are rebuilt next tick
+    fn save(&self, out: &mut SaveWriter) {
+        out.section("world");
+        out.write(&self.world.entity_allocator);
+        self.world.save_storage::<RenderComponent>(out);
+        self.world.save_storage::<MovementComponent>(out);
+        self.world.save_storage::<CollisionComponent>(out);
+        self.world.save_storage::<EnergyComponent>(out);
+        self.world.save_storage::<EnemyAIComponent>(out);
+        self.world.save_storage::<DoorComponent>(out);
+        self.world.save_storage::<PushableComponent>(out);
+        self.world.save_storage::<HealthComponent>(out);
+        self.world.save_storage::<AttackComponent>(out);
+        self.world.save_storage::<DefenseComponent>(out);
+        self.world.save_storage::<FactionComponent>(out);
+
+        out.section("resources");
+        out.write(&self.tilemap);
+        out.write(&self.fov);
+        out.write(&self.turn_scheduler);
+        out.write(&self.log);
+        out.write(&self.player);
+        out.write(&self.movement);
+        out.write(&self.rng);
+        out.write(&self.renderer.camera.offset);
+    }
+
+    // same order as save
+    fn load(&mut self, input: &mut SaveReader) -> std::io::Result<()> {
+        input.section("world")?;
+        self.world.entity_allocator = input.read()?;
+        self.world.load_storage::<RenderComponent>(input)?;
+        self.world.load_storage::<MovementComponent>(input)?;
+        self.world.load_storage::<CollisionComponent>(input)?;
+        self.world.load_storage::<EnergyComponent>(input)?;
+        self.world.load_storage::<EnemyAIComponent>(input)?;
+        self.world.load_storage::<DoorComponent>(input)?;
+        self.world.load_storage::<PushableComponent>(input)?;
+        self.world.load_storage::<HealthComponent>(input)?;
+        self.world.load_storage::<AttackComponent>(input)?;
+        self.world.load_storage::<DefenseComponent>(input)?;
+        self.world.load_storage::<FactionComponent>(input)?;
+
+        input.section("resources")?;
+        let tilemap: TileMap = input.read()?;
+        if tilemap.dimensions != self.tilemap.dimensions {
+            return Err(corrupt("saved map is a different size"));
+        }
+        self.tilemap = tilemap;
+        self.fov = input.read()?;
+        self.turn_scheduler = input.read()?;
+        self.log = input.read()?;
+        self.player = input.read()?;
+        let movement = input.read()?;
+        self.set_movement(movement);
+        self.rng = input.read()?;
+        self.renderer.camera.offset = input.read()?;
+        self.check_loaded()?;
+
+        self.despawn_queue.clear();
+        self.dirty = true;
+        Ok(())
+    }
+
+    // A save that reads fine can still not fit this map,
+    // better an error now than a panic drawing it later
+    fn check_loaded(&self) -> std::io::Result<()> {
+        if self.fov.dimensions() != self.tilemap.dimensions {
+            return Err(corrupt("saved field of view is a different size to the map"));
+        }
+
+        if let Some(player) = self.player {
+            if !self.world.is_live(player) {
+                return Err(corrupt("saved player is not in the world"));
+            }
+        }
+
+        for (entity, comp) in self.world.borrow::<RenderComponent>().iter() {
+            if !self.world.is_live(entity) {
+                return Err(corrupt(&format!("entity {} has components but is not alive", entity.index())));
+            }
+
+            let width = comp.matrix.len();
+            let height = comp.matrix.iter().map(|col| col.len()).max().unwrap_or(0);
+            let far_corner = (
+                comp.position_tl.0 as usize + width.saturating_sub(1),
+                comp.position_tl.1 as usize + height.saturating_sub(1),
+            );
+            if !self.tilemap.in_bounds(comp.position_tl) || far_corner.0 >= self.tilemap.dimensions.0 || far_corner.1 >= self.tilemap.dimensions.1 {
+                return Err(corrupt(&format!("entity {} is off the map", entity.index())));
+            }
+        }
+
+        let offset = self.renderer.camera.offset;
+        if offset.0 as usize >= self.tilemap.dimensions.0 || offset.1 as usize >= self.tilemap.dimensions.1 {
+            return Err(corrupt("saved camera is off the map"));
+        }
+
+        Ok(())
+    }
+
+    fn save_to(&self, path: &str) -> std::io::Result<()> {
+        let mut out = SaveWriter::new();
+        self.save(&mut out);
+        out.to_file(path)
+    }
+
+    fn load_from(&mut self, path: &str) -> std::io::Result<()> {
+        let mut input = SaveReader::from_file(path)?;
+        self.load(&mut input)
     }
 
     fn move_entity(&mut self, entity: Entity, direction: Direction) {
-        match self.movement_components.get_mut(entity) {
-            Some(component) => {
+        match self.world.get_mut::<MovementComponent>(entity) {
+            Some(mut component) => {
                 component.move_desired(direction);
             },
             None => panic!("No movement comp on entity!"),
         }
     }
 
-    fn handle_input(&mut self, input: Event) {
+    // what the key means with the current keymap, None if nothing
+    fn input_action(&self, input: Event) -> Option<InputAction> {
+        match input {
+            Event::Key(key_event) => self.keymap.action(key_event.code),
+            _ => None,
+        }
+    }
+
+    fn handle_input(&mut self, action: InputAction) {
+        if self.menu.is_some() {
+            self.handle_menu_input(action);
+            return;
+        }
+
+        match action {
+            InputAction::Quit => self.running = false,
+            InputAction::OpenMenu => self.menu = Some(Menu::new("Paused", &["Resume", "Quit"])),
+            InputAction::ToggleOverlay(overlay) => self.toggle_overlay(overlay),
+            // reading the log is free
+            InputAction::ScrollLogUp => self.log.scroll_up(1),
+            InputAction::ScrollLogDown => self.log.scroll_down(1),
+            // A held key repeats faster than turns come round, only the
+            // newest is kept so letting go stops the player straight away
+            action if action.takes_turn() => {
+                self.queued_action = Some(action);
+                self.take_queued_action();
+            },
+            _ => (),
+        }
+    }
+
+    fn handle_menu_input(&mut self, action: InputAction) {
+        let menu = match &mut self.menu {
+            Some(menu) => menu,
+            None => return,
+        };
+
+        match action {
+            InputAction::Move(Direction::Up) => menu.select_previous(),
+            InputAction::Move(Direction::Down) => menu.select_next(),
+            InputAction::OpenMenu => self.menu = None,
+            InputAction::Interact => {
+                if let Some("Quit") = menu.selected_item() {
+                    self.running = false;
+                }
+                self.menu = None;
+            },
+            _ => (),
+        }
+    }
+
+    // everything that costs a turn has to wait for the players turn
+    fn take_queued_action(&mut self) {
         let player = match self.player {
             Some(e) => e,
-            None => panic!("No player!"),
+            None => return,
         };
-        match input {
-            Event::Key(key_event) => {
-                match key_event.code {
-                    KeyCode::Char('q') => self.running = false,
-                    KeyCode::Right => {
-                        self.move_entity(player, Direction::Right);
-                        self.add_energy(10);
-                    },
-                    KeyCode::Left => {
-                        self.move_entity(player, Direction::Left);
-                        self.add_energy(10);
-                    },
-                    KeyCode::Up => {
-                        self.move_entity(player, Direction::Up);
-                        self.add_energy(10);
-                    },
-                    KeyCode::Down => {
-                        self.move_entity(player, Direction::Down);
-                        self.add_energy(10);
-                    },
-                    _ => return,
-                }
+        if !self.is_player_turn() {
+            return;
+        }
+        let action = match self.queued_action.take() {
+            Some(action) => action,
+            None => return,
+        };
+
+        match action {
+            InputAction::Wait => {
+                self.end_player_turn(WAIT_COST);
             },
-            _ => return,
+            // nothing there to use, no turn spent
+            InputAction::Interact if self.interact(player) => {
+                self.end_player_turn(MOVE_COST);
+            },
+            InputAction::Move(direction) if self.movement.allows(direction) => {
+                self.move_entity(player, direction);
+                self.end_player_turn(MOVE_COST);
+            },
+            _ => (),
         }
     }
 
-    fn add_energy(&mut self, ammount: u8) {
-        for gen_index in self.entity_allocator.get_vec() {
-            let comp = match self.energy_components.get_mut(gen_index) {
-                Some(comp) => comp,
-                None => continue,
-            };
+    // debug overlays are drawn every frame while their system is on
+    fn toggle_overlay(&mut self, overlay: DebugOverlay) {
+        let on = self.schedule.toggle(overlay.system_name());
+        let state = match on {
+            true => "on",
+            false => "off",
+        };
+
+        self.log.info(&format!("{} overlay {}.", overlay.name(), state));
+        self.dirty = true;
+    }
 
-            comp.energy += ammount;
+    fn is_player_turn(&self) -> bool {
+        match self.player {
+            Some(player) => self.turn_scheduler.peek() == Some(player),
+            None => false,
         }
     }
 
+    fn end_player_turn(&mut self, cost: u8) {
+        let player = match self.player {
+            Some(e) => e,
+            None => panic!("No player!"),
+        };
+        let speed = match self.world.get::<EnergyComponent>(player) {
+            Some(comp) => comp.speed,
+            None => NORMAL_SPEED,
+        };
+
+        self.turn_scheduler.pop();
+        self.turn_scheduler.schedule(player, action_delay(cost, speed));
+    }
+
     fn handle_render(&mut self) {
+        // keep the player on screen, before drawing so the
+        // map is drawn for where the camera ends up
+        if let Some(player) = self.player {
+            if let Some(comp) = self.world.get::<RenderComponent>(player) {
+                let position = comp.position_tl;
+                self.renderer.follow(position);
+            }
+        }
+
+        self.renderer.draw_tilemap(&self.tilemap, &self.fov);
+
         let mut layers: Vec<Vec<(Dimemsion, Buffer)>> = vec![
             vec![],
             vec![],
             vec![],
         ];
-        for gen_index in self.entity_allocator.get_vec() {
-            let comp = match self.render_components.get_mut(gen_index) {
-                Some(comp) => comp,
-                None => continue,
-            };
-                
-            // Handling Begins
-
+        for (_, comp) in self.world.borrow_mut::<RenderComponent>().iter_mut() {
             if comp.visible {
                 let tl = comp.position_tl;
                 //let br = comp.position_br;
@@ -331,119 +750,430 @@ impl GameState {
         }
         for layer in layers.iter().rev() {
             for matrix in layer {
-                self.renderer.insert_matrix(matrix.0, &matrix.1);
+                self.insert_matrix_visible(matrix.0, &matrix.1);
             }
         }
     }
 
+    // like insert_matrix, but skips cells the player can't see
+    fn insert_matrix_visible(&mut self, location: (u16, u16), matrix: &Buffer) {
+        for (x, col) in matrix.iter().enumerate() {
+            for (y, value) in col.iter().enumerate() {
+                let position = (location.0 + x as u16, location.1 + y as u16);
+
+                if self.fov.is_visible(position) {
+                    self.renderer.input_buffer[position.0 as usize][position.1 as usize] = *value;
+                }
+            }
+        }
+    }
+
+    // health in the corner, and whatever menu is open
+    fn draw_hud(&mut self) {
+        let health = match self.player {
+            Some(player) => self.world.get::<HealthComponent>(player).map(|h| (h.current, h.max)),
+            None => None,
+        };
+
+        let ui = &mut self.renderer.ui;
+        ui.clear();
+
+        if let Some((current, max)) = health {
+            let area = Rect::new(0, 0, 24, 3);
+            ui.draw(&Panel::new(Some("Player")), area);
+
+            let bar = ProgressBar::new(current, max, Color::DarkRed)
+                .with_label(&format!("HP {}/{}", current, max));
+            ui.draw(&bar, area.inner());
+        }
+
+        // just above the log, so it's clear why new messages don't show
+        if self.log.is_scrolled() {
+            let label = Label::new("Log scrolled back, PageDown for newer", Color::Yellow);
+            let y = (ui.height as u16).saturating_sub(1);
+            ui.draw(&label, Rect::new(0, y, ui.width as u16, 1));
+        }
+
+        if let Some(menu) = &self.menu {
+            let (width, height) = menu.size();
+            let x = (ui.width as u16).saturating_sub(width) / 2;
+            let y = (ui.height as u16).saturating_sub(height) / 2;
+
+            ui.draw(menu, Rect::new(x, y, width, height));
+        }
+    }
+
+    // newest at the bottom of the panel
+    fn draw_message_log(&mut self) {
+        let rows = self.renderer.panel_rows as usize;
+        let visible = self.log.visible(rows);
+        let blank = rows - visible.len();
+
+        for row in 0..rows {
+            let line = match row.checked_sub(blank) {
+                Some(i) => visible[i].to_line(),
+                None => Vec::new(),
+            };
+            self.renderer.set_panel_line(row, line);
+        }
+    }
+
+    fn handle_fov(&mut self) {
+        let player = match self.player {
+            Some(e) => e,
+            None => return,
+        };
+
+        if let Some(comp) = self.world.get::<RenderComponent>(player) {
+            self.fov.compute(comp.position_tl, &self.tilemap);
+        }
+    }
+
+    // everyone whose turn comes before the players next one gets to act
     fn handle_enemy_energy_move(&mut self) {
-        for gen_index in self.entity_allocator.get_vec() {
-            let ai_type: AIType = match self.enemy_ai_components.get(gen_index) {
-                Some(ai_type) => ai_type.ai_type.clone(),
-                None => continue,
+        let energy_components = self.world.borrow::<EnergyComponent>();
+        let ai_components = self.world.borrow::<EnemyAIComponent>();
+        let render_components = self.world.borrow::<RenderComponent>();
+        let mut movement_components = self.world.borrow_mut::<MovementComponent>();
+
+        // everyone who can act, looked up in turn order below
+        let mut actors: HashMap<Entity, (u32, &AIType, &mut MovementComponent)> = join3_mut(&energy_components, &ai_components, &mut movement_components)
+            .map(|(entity, energy, ai, movement)| (entity, (energy.speed, &ai.ai_type, movement)))
+            .collect();
+
+        // in case the player is not scheduled, dont loop forever
+        let mut budget = self.turn_scheduler.len();
+
+        while budget > 0 {
+            let gen_index = match self.turn_scheduler.peek() {
+                Some(e) if Some(e) == self.player => break,
+                Some(e) => e,
+                None => break,
             };
-            
-            let render_comp = match self.render_components.get(gen_index) {
-                Some(comp) => comp,
-                None => continue,
+            self.turn_scheduler.pop();
+            budget -= 1;
+
+            if !self.world.is_live(gen_index) {
+                continue;
+            }
+
+            let position = render_components.get(gen_index).map(|comp| comp.position_tl);
+
+            let (speed, ai_type, move_comp, position) = match (actors.get_mut(&gen_index), position) {
+                (Some((speed, ai_type, move_comp)), Some(position)) => (*speed, *ai_type, move_comp, position),
+                // can't act without all of them, but if it still has
+                // energy it waits and keeps its place in the scheduler
+                _ => {
+                    if let Some(energy) = energy_components.get(gen_index) {
+                        self.turn_scheduler.schedule(gen_index, action_delay(WAIT_COST, energy.speed));
+                    }
+                    continue;
+                },
             };
 
-            let comp = match self.energy_components.get_mut(gen_index) {
-                Some(comp) => comp,
-                None => continue,
+            let (action, cost) = match ai_type {
+                AIType::SimpleDown => simple_down(position, &self.collision_buffer, &self.tilemap),
+                AIType::SimpleLeft => (Action::Move(Direction::Left), MOVE_COST),
+                AIType::RollDownPlayer => roll_down_player(position, &self.collision_buffer, &self.tilemap, &self.player_dijk.current_generation, self.movement, &mut self.rng),
+                AIType::SeekPosition(goal) => seek_position(position, *goal, &mut move_comp.path, &self.collision_buffer, &self.tilemap, self.movement),
+                AIType::Desires(desires) => {
+                    // maps nobody has made yet are left out
+                    let maps: Vec<(&DesireMap, f32)> = desires
+                        .iter()
+                        .filter_map(|(desire, weight)| self.desire_maps.get(desire).map(|map| (map, *weight)))
+                        .collect();
+
+                    roll_down_desires(position, &self.collision_buffer, &self.tilemap, &maps, self.movement)
+                },
             };
 
-            let move_comp = match self.movement_components.get_mut(gen_index) {
-                Some(component) => component,
-                None => continue,
-            };         
-            if comp.energy >= 10 {
-                match ai_type {
-                    AIType::SimpleDown => {
-                        match simple_down(render_comp.position_tl, &self.collision_buffer) {
-                            (Action::DoNothing, x) => {
-                                comp.energy -= x;
-                            },
-                            (Action::Move(dir), x) => {
-                                move_comp.move_desired(dir);
-                                comp.energy -= x;
-                            },
-                        }
-                    },
-                    AIType::SimpleLeft => {
-                        move_comp.move_desired(Direction::Left);
-                        comp.energy -= 10;
-                    },
-                    AIType::RollDownPlayer => {
-                        match roll_down_player(render_comp.position_tl, &self.collision_buffer, &self.player_dijk.current_generation) {
-                            (Action::DoNothing, x) => {
-                                comp.energy -= x;
-                            },
-                            (Action::Move(dir), x) => {
-                                move_comp.move_desired(dir);
-                                //self.move_entity(gen_index, dir);
-                                comp.energy -= x;
-                            },
-                        }
-                    }
-                }
+            match action {
+                Action::DoNothing => (),
+                Action::Move(dir) => move_comp.move_desired(dir),
+                Action::FollowPath => {
+                    move_comp.follow_path(position);
+                },
             }
+
+            self.turn_scheduler.schedule(gen_index, action_delay(cost, speed));
         }
     }
 
-    fn handle_movement(&mut self) {
-        for gen_index in self.entity_allocator.get_vec() {
-            let comp = match self.movement_components.get_mut(gen_index) {
-                Some(comp) => comp,
-                None => continue,
-            };
-                
+    // returns true if anything moved
+    fn handle_movement(&mut self) -> bool {
+        let mut moved = false;
+
+        let mut movement_components = self.world.borrow_mut::<MovementComponent>();
+        let mut render_components = self.world.borrow_mut::<RenderComponent>();
+
+        for (entity, comp, render_comp) in join_both_mut(&mut movement_components, &mut render_components) {
             let (desired_x, desired_y): (u16, u16) = (comp.desired_position.0, comp.desired_position.1);
+            let not_moved: bool = comp.desired_position == render_comp.position_tl;
 
-            match self.render_components.get_mut(gen_index) {
-                Some(render_comp) => {
-                    let collided: bool = self.collision_buffer[desired_x as usize][desired_y as usize] == 1;
-                    let not_moved: bool = comp.desired_position == render_comp.position_tl;
-                    
-                    // test if collided then stop doing anything after
-                    if collided || not_moved {
-                        comp.desired_position = render_comp.position_tl;
-                        continue;
-                    }
+            // off the map never gets as far as the buffers
+            let collided: bool = !self.tilemap.in_bounds((desired_x, desired_y)) ||
+                !self.tilemap.is_walkable((desired_x, desired_y));
 
-                    //let dif_x = 0; not in use
-                    //let dif_y = 0;
-                    
-                    render_comp.backgroud = self.renderer.input_buffer[desired_x as usize][desired_y as usize];// + render_comp.character;
-                    render_comp.position_tl = (desired_x, desired_y);
-                    },
-                None => continue,
+            // the index is kept up to date as things move, so two
+            // entities cant end up on the same cell in one pass
+            let blocker = match not_moved || collided {
+                true => None,
+                false => self.spatial_index.blocker((desired_x, desired_y), entity),
+            };
+            if let Some(blocker) = blocker {
+                self.collision_events.push(CollisionEvent {
+                    mover: entity,
+                    blocker,
+                    position: (desired_x, desired_y),
+                });
+            }
+            
+            // test if collided then stop doing anything after
+            if collided || not_moved || blocker.is_some() {
+                comp.desired_position = render_comp.position_tl;
+                continue;
+            }
+            
+            self.spatial_index.move_entity(render_comp.position_tl, (desired_x, desired_y), entity);
+            if let Some(glyph) = self.tilemap.glyph((desired_x, desired_y)) {
+                render_comp.backgroud = glyph;
+            }
+            render_comp.position_tl = (desired_x, desired_y);
+            moved = true;
+        }
+
+        if moved {
+            self.dirty = true;
+        }
+
+        moved
+    }
+
+    // What happens when something walks into something else
+    fn handle_bumps(&mut self) {
+        for event in std::mem::take(&mut self.collision_events) {
+            if !self.world.is_live(event.mover) || !self.world.is_live(event.blocker) {
+                continue;
+            }
+
+            let closed_door = match self.world.get::<DoorComponent>(event.blocker) {
+                Some(door) => !door.open,
+                None => false,
+            };
+
+            if self.is_hostile(event.mover, event.blocker) {
+                self.attack(event.mover, event.blocker);
+            } else if closed_door {
+                self.open_door(event.blocker);
+            } else if self.world.has::<PushableComponent>(event.blocker) {
+                self.push(event.mover, event.blocker);
+            }
+        }
+    }
+
+    fn is_hostile(&self, a: Entity, b: Entity) -> bool {
+        match (self.world.get::<FactionComponent>(a), self.world.get::<FactionComponent>(b)) {
+            (Some(a), Some(b)) => a.is_hostile(&b),
+            _ => false,
+        }
+    }
+
+    // Only things with health can be hit, only things with attack hit
+    fn attack(&mut self, attacker: Entity, target: Entity) {
+        let amount = match self.world.get::<AttackComponent>(attacker) {
+            Some(attack) => resolve_attack(&attack, self.world.get::<DefenseComponent>(target).as_deref()),
+            None => return,
+        };
+
+        let killed = match self.world.get_mut::<HealthComponent>(target) {
+            Some(mut health) => health.take_damage(amount),
+            None => return,
+        };
+
+        self.damage_events.push(DamageEvent {
+            attacker,
+            target,
+            amount,
+            killed,
+        });
+
+        if self.player == Some(attacker) {
+            self.log.info(&format!("You hit it for {} damage.", amount));
+        } else if self.player == Some(target) {
+            self.log.danger(&format!("It hits you for {} damage.", amount));
+        }
+        self.dirty = true;
+    }
+
+    // the dead go in the despawn queue, losing the player ends the game
+    fn handle_deaths(&mut self) {
+        for event in std::mem::take(&mut self.damage_events) {
+            if !event.killed {
+                continue;
+            }
+
+            if self.player == Some(event.target) {
+                self.log.danger("You die...");
+                self.running = false;
+            } else {
+                self.log.warn("It dies.");
             }
+            self.queue_despawn(event.target);
+        }
+    }
+
+    fn open_door(&mut self, door: Entity) {
+        let open_character = match self.world.get_mut::<DoorComponent>(door) {
+            Some(mut component) => {
+                component.open = true;
+                component.open_character
+            },
+            None => return,
+        };
+
+        if let Some(mut render_comp) = self.world.get_mut::<RenderComponent>(door) {
+            render_comp.character = open_character;
+            self.spatial_index.remove(render_comp.position_tl, door);
         }
+        self.world.remove::<CollisionComponent>(door);
+        self.log.info("The door opens.");
+        self.dirty = true;
     }
 
+    // Nothing happens if something is standing in the doorway
+    fn close_door(&mut self, door: Entity) -> bool {
+        let (position, closed_character) = match (self.world.get::<RenderComponent>(door), self.world.get::<DoorComponent>(door)) {
+            (Some(render_comp), Some(door_comp)) => (render_comp.position_tl, door_comp.closed_character),
+            _ => return false,
+        };
+        if self.spatial_index.is_occupied(position) {
+            self.log.warn("Something is in the way.");
+            return false;
+        }
+
+        if let Some(mut component) = self.world.get_mut::<DoorComponent>(door) {
+            component.open = false;
+        }
+        if let Some(mut render_comp) = self.world.get_mut::<RenderComponent>(door) {
+            render_comp.character = closed_character;
+        }
+        self.world.insert(door, CollisionComponent::new(position, get_matrix(position, position, 1), 1));
+        self.spatial_index.insert(position, door);
+        self.log.info("The door closes.");
+        self.dirty = true;
+        true
+    }
+
+    // Opens or closes any door next to the entity, true if one did
+    fn interact(&mut self, entity: Entity) -> bool {
+        let position = match self.world.get::<RenderComponent>(entity) {
+            Some(comp) => comp.position_tl,
+            None => return false,
+        };
+        let around: Vec<(u16, u16)> = self.movement.directions()
+            .iter()
+            .filter_map(|direction| self.tilemap.step(position, *direction))
+            .collect();
+
+        let doors: Vec<(Entity, bool)> = join(&self.world.borrow::<DoorComponent>(), &self.world.borrow::<RenderComponent>())
+            .filter(|(_, _, render_comp)| around.contains(&render_comp.position_tl))
+            .map(|(door, door_comp, _)| (door, door_comp.open))
+            .collect();
+
+        if doors.is_empty() {
+            self.log.info("Nothing to do here.");
+            return false;
+        }
+
+        let mut changed = false;
+        for (door, open) in doors {
+            if open {
+                changed |= self.close_door(door);
+            } else {
+                self.open_door(door);
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    // Moves the blocker one cell away from the mover, the mover
+    // takes its place. Nothing happens if the far side is taken
+    fn push(&mut self, mover: Entity, blocker: Entity) {
+        let from = match self.world.get::<RenderComponent>(mover) {
+            Some(comp) => comp.position_tl,
+            None => return,
+        };
+        let position = match self.world.get::<RenderComponent>(blocker) {
+            Some(comp) => comp.position_tl,
+            None => return,
+        };
+
+        let offset = (position.0 as i32 - from.0 as i32, position.1 as i32 - from.1 as i32);
+        let target = match Direction::from_offset(offset).and_then(|dir| self.tilemap.step(position, dir)) {
+            Some(target) => target,
+            None => return,
+        };
+
+        if !self.tilemap.is_walkable(target) || self.spatial_index.is_occupied(target) {
+            return;
+        }
+
+        if let Some(mut comp) = self.world.get_mut::<RenderComponent>(blocker) {
+            if let Some(glyph) = self.tilemap.glyph(target) {
+                comp.backgroud = glyph;
+            }
+            comp.position_tl = target;
+        }
+        self.spatial_index.move_entity(position, target, blocker);
+
+        if let Some(mut comp) = self.world.get_mut::<RenderComponent>(mover) {
+            if let Some(glyph) = self.tilemap.glyph(position) {
+                comp.backgroud = glyph;
+            }
+            comp.position_tl = position;
+        }
+        if let Some(mut comp) = self.world.get_mut::<MovementComponent>(mover) {
+            comp.desired_position = position;
+        }
+        self.spatial_index.move_entity(from, position, mover);
+
+        self.dirty = true;
+    }
+
+    fn has_pending_moves(&self) -> bool {
+        let movement_components = self.world.borrow::<MovementComponent>();
+        let render_components = self.world.borrow::<RenderComponent>();
+
+        return join(&movement_components, &render_components)
+            .any(|(_, comp, render_comp)| comp.desired_position != render_comp.position_tl);
+    }
+
+    // only entities end up in the collision buffer, walls come from the tilemap
     fn handle_collision(&mut self) {
-        self.collision_buffer = self.empty_buffer.clone();
+        for col in self.collision_buffer.iter_mut() {
+            for val in col.iter_mut() {
+                *val = 0;
+            }
+        }
 
         let mut layers: Vec<Vec<(Dimemsion, Buffer)>> = vec![
             vec![],
             vec![],
             vec![],
         ];
-        for gen_index in self.entity_allocator.get_vec() {
-            let comp = match self.collision_components.get_mut(gen_index) {
-                Some(comp) => comp,
-                None => continue,
-            };
-                
-            match self.render_components.get(gen_index) {
-                Some(render_comp) => {
-                    comp.position = render_comp.position_tl;
-                    comp.matrix = render_comp.matrix.clone();
-                },
-                None => (),
-            }
+        self.spatial_index.clear();
+        let render_components = self.world.borrow::<RenderComponent>();
+        let mut collision_components = self.world.borrow_mut::<CollisionComponent>();
+
+        // colliders follow what is drawn
+        for (_, render_comp, comp) in join_mut(&render_components, &mut collision_components) {
+            comp.position = render_comp.position_tl;
+            comp.matrix = render_comp.matrix.clone();
+        }
+        for (gen_index, comp) in collision_components.iter() {
             let matrix: Buffer = comp.matrix.clone();//get_matrix(tl, br, comp.backgroud + comp.character);
+            self.spatial_index.insert_matrix(comp.position, &matrix, gen_index);
             layers[comp.layer as usize].push((comp.position, matrix));
         }
         for layer in layers.iter().rev() {
@@ -459,61 +1189,187 @@ impl GameState {
         //
     }
 
-    fn add_entity(&mut self, components: Vec<ComponentList>) -> Entity {
-        let entity = self.entity_allocator.allocate();
+    // debug overlays go on the ui layer after the hud, so over the map
+    fn draw_collision_debug(&mut self) {
+        let offset = self.renderer.camera.offset;
+        draw_collision(&mut self.renderer.ui, offset, &self.collision_buffer);
+    }
 
-        for component in components {
-            match component {
-                ComponentList::Render(comp) => self.render_components.set(entity, comp),
-                ComponentList::Collision(comp) => self.collision_components.set(entity, comp),
-                ComponentList::Energy(comp) => self.energy_components.set(entity, comp),
-                ComponentList::Movement(comp) => self.movement_components.set(entity, comp),
-            }
+    fn draw_dijkstra_debug(&mut self) {
+        let offset = self.renderer.camera.offset;
+        draw_dijkstra(&mut self.renderer.ui, offset, &self.player_dijk);
+    }
+
+    // next to the entity, not on it
+    fn draw_entity_id_debug(&mut self) {
+        let offset = self.renderer.camera.offset;
+        let ui = &mut self.renderer.ui;
+
+        for (entity, render_comp) in self.world.borrow::<RenderComponent>().iter() {
+            let position = (render_comp.position_tl.0.saturating_add(1), render_comp.position_tl.1);
+            draw_label(ui, offset, position, &entity.index().to_string(), Color::Yellow);
         }
+    }
+
+    // the next step and path of every AI, what it is after written above it
+    fn draw_ai_intent_debug(&mut self) {
+        let offset = self.renderer.camera.offset;
+        let ui = &mut self.renderer.ui;
+
+        let ai_components = self.world.borrow::<EnemyAIComponent>();
+        let movement_components = self.world.borrow::<MovementComponent>();
+        let render_components = self.world.borrow::<RenderComponent>();
 
-        return entity;
+        for (entity, ai_comp, movement_comp) in join(&ai_components, &movement_components) {
+            let position = match render_components.get(entity) {
+                Some(comp) => comp.position_tl,
+                None => continue,
+            };
+
+            draw_intent(ui, offset, position, movement_comp.desired_position, movement_comp.path.as_ref());
+            if let Some(above) = position.1.checked_sub(1) {
+                draw_label(ui, offset, (position.0, above), &intent_name(&ai_comp.ai_type), Color::Magenta);
+            }
+        }
     }
 
-    fn init_player(&mut self, position: (u16, u16)) {
-        let mut comps: Vec<ComponentList> = Vec::new();
-        comps.push(ComponentList::Render(RenderComponent::new(1, 1, position, get_matrix(position, position, 1), 0)));
-        comps.push(ComponentList::Movement(MovementComponent::new(position)));
-        comps.push(ComponentList::Collision(CollisionComponent::new(position, get_matrix(position, position, 1), 0)));
+    // removes straight away, dont use while iterating, use queue_despawn
+    fn despawn(&mut self, entity: Entity) -> bool {
+        if !self.world.despawn(entity) {
+            return false;
+        }
+        self.turn_scheduler.remove(entity);
 
-        let player_entity = Some(self.add_entity(comps));
+        if self.player == Some(entity) {
+            self.player = None;
+        }
 
-        self.player = player_entity;
+        true
     }
 
-    fn init_map(&mut self, percentage: f64) {
-        let entity = self.entity_allocator.allocate();
+    fn queue_despawn(&mut self, entity: Entity) {
+        if !self.despawn_queue.contains(&entity) {
+            self.despawn_queue.push(entity);
+        }
+    }
 
-        self.map_generator.randomize(percentage);
-        self.map_generator.generate();
+    fn flush_despawns(&mut self) {
+        for entity in std::mem::take(&mut self.despawn_queue) {
+            self.despawn(entity);
+        }
+    }
 
-        let collision_comp = CollisionComponent::new((0, 0), self.map_generator.current_generation.clone(), 2);
-        let render_comp = RenderComponent::new(2, 1, (0, 0), self.map_generator.current_generation.clone(), 2); 
+    fn init_player(&mut self, position: (u16, u16)) {
+        let player_entity = self.world.spawn()
+            .with(RenderComponent::new(1, 1, position, get_matrix(position, position, 1), 0))
+            .with(MovementComponent::new(position))
+            .with(CollisionComponent::new(position, get_matrix(position, position, 1), 0))
+            .with(EnergyComponent::new(NORMAL_SPEED))
+            .with(HealthComponent::new(30))
+            .with(AttackComponent::new(5))
+            .with(DefenseComponent::new(1))
+            .with(FactionComponent::new(Faction::Player))
+            .build();
+        self.turn_scheduler.schedule(player_entity, 0);
+
+        self.player = Some(player_entity);
+    }
 
-        self.collision_components.set(entity, collision_comp);
-        self.render_components.set(entity, render_comp);
+    // everything a new game starts with, replays start from it too
+    fn init_test_level(&mut self, random_map: bool) {
+        self.init_player((5, 5));
+        self.init_test_enemy((22, 18), NORMAL_SPEED);
+        self.init_test_enemy((40, 40), NORMAL_SPEED * 2); // fast
+        self.init_test_enemy((22, 15), NORMAL_SPEED / 2); // slow
+        self.init_test_seeker((60, 40), (5, 5));
+        self.init_test_coward((30, 30));
+        self.init_door((8, 5));
+        self.init_crate((5, 8));
+
+        if random_map {
+            self.init_map(0.45); // 0.5 is good
+        } else {
+            self.init_background();
+            self.init_borders();
+            self.init_field();
+        }
     }
 
-    fn init_test_enemy(&mut self, position: (u16, u16)) {
-        let entity = self.entity_allocator.allocate();
+    fn init_map(&mut self, percentage: f64) {
+        self.map_generator.randomize(percentage, &mut self.rng);
+        self.map_generator.generate();
 
+        self.tilemap = TileMap::from_generation(&self.map_generator.current_generation);
+        self.fov.reset();
+    }
+
+    fn init_test_enemy(&mut self, position: (u16, u16), speed: u32) {
         let render_comp = RenderComponent::new(3, 1, position, get_matrix(position, position, 1), 0);
         let movement_comp = MovementComponent::new(position);
         let collision_comp = CollisionComponent::new(position, get_matrix(position, position, 1), 0);
-        let energy_comp = EnergyComponent::new(0);
+        let energy_comp = EnergyComponent::new(speed);
         let enemy_ai_comp = EnemyAIComponent::new(AIType::RollDownPlayer);
 
-        self.render_components.set(entity, render_comp);
-        self.movement_components.set(entity, movement_comp);
-        self.collision_components.set(entity, collision_comp);
-        self.energy_components.set(entity, energy_comp);
-        self.enemy_ai_components.set(entity, enemy_ai_comp);
+        let entity = self.world.spawn()
+            .with(render_comp)
+            .with(movement_comp)
+            .with(collision_comp)
+            .with(energy_comp)
+            .with(enemy_ai_comp)
+            .with(HealthComponent::new(10))
+            .with(AttackComponent::new(3))
+            .with(FactionComponent::new(Faction::Monster))
+            .build();
+        self.turn_scheduler.schedule(entity, 0);
     }
     
+    // walks to goal with A*
+    fn init_test_seeker(&mut self, position: (u16, u16), goal: (u16, u16)) {
+        let entity = self.world.spawn()
+            .with(RenderComponent::new(3, 1, position, get_matrix(position, position, 1), 0))
+            .with(MovementComponent::new(position))
+            .with(CollisionComponent::new(position, get_matrix(position, position, 1), 0))
+            .with(EnergyComponent::new(NORMAL_SPEED))
+            .with(EnemyAIComponent::new(AIType::SeekPosition(goal)))
+            .with(HealthComponent::new(10))
+            .with(FactionComponent::new(Faction::Monster))
+            .build();
+        self.turn_scheduler.schedule(entity, 0);
+    }
+
+    // keeps its distance, runs but not too hard
+    fn init_test_coward(&mut self, position: (u16, u16)) {
+        let desires = vec![(Desire::FleePlayer, 1.0), (Desire::ChasePlayer, 0.5)];
+
+        let entity = self.world.spawn()
+            .with(RenderComponent::new(3, 1, position, get_matrix(position, position, 1), 0))
+            .with(MovementComponent::new(position))
+            .with(CollisionComponent::new(position, get_matrix(position, position, 1), 0))
+            .with(EnergyComponent::new(NORMAL_SPEED))
+            .with(EnemyAIComponent::new(AIType::Desires(desires)))
+            .with(HealthComponent::new(10))
+            .with(FactionComponent::new(Faction::Monster))
+            .build();
+        self.turn_scheduler.schedule(entity, 0);
+    }
+
+    // closed '+' until bumped, then an open '\''
+    fn init_door(&mut self, position: (u16, u16)) {
+        self.world.spawn()
+            .with(RenderComponent::new(36, 1, position, get_matrix(position, position, 1), 1))
+            .with(CollisionComponent::new(position, get_matrix(position, position, 1), 1))
+            .with(DoorComponent::new(36, 37))
+            .build();
+    }
+
+    fn init_crate(&mut self, position: (u16, u16)) {
+        self.world.spawn()
+            .with(RenderComponent::new(38, 1, position, get_matrix(position, position, 1), 1))
+            .with(CollisionComponent::new(position, get_matrix(position, position, 1), 1))
+            .with(PushableComponent)
+            .build();
+    }
+
     fn init_borders(&mut self) {
         let top = ((1, 1), (self.renderer.view_port.0, 1));
         let left = ((1, 1), (1, self.renderer.view_port.1));
@@ -522,32 +1378,50 @@ impl GameState {
         let test = ((20, 20), (30, 30));
 
         let borders = vec![top, bottom, left, right, test];
-        // add collision comp and render comp
 
         for border in borders {
-            let entity = self.entity_allocator.allocate();
-            
-            let collision_comp = CollisionComponent::new(border.0, get_matrix(border.0, border.1, 1), 0);
-            let render_comp = RenderComponent::new(2, 1, border.0, get_matrix(border.0, border.1, 1), 0);
-            
-            self.collision_components.set(entity, collision_comp);
-            self.render_components.set(entity, render_comp);
+            self.tilemap.fill_rect(border.0, border.1, MapTile::wall());
         }
     }
 
     fn init_field(&mut self) {
-        let entity = self.entity_allocator.allocate();
-
         let render_comp = RenderComponent::new(0, 5, (50, 10), get_matrix((50, 10), (70, 30), 1), 1);
-        self.render_components.set(entity, render_comp);
+        self.world.spawn().with(render_comp).build();
     }
 
     fn init_background(&mut self) {
-        let entity = self.entity_allocator.allocate();
-        let dimensions = self.renderer.dimensions;
+        self.tilemap.fill(MapTile::floor());
+        self.fov.reset();
+    }
+}
+
+impl Game for GameState {
+    fn running(&self) -> bool {
+        self.running
+    }
+
+    fn action(&self, event: Event) -> Option<InputAction> {
+        self.input_action(event)
+    }
+
+    fn handle_action(&mut self, action: InputAction) {
+        self.handle_input(action);
+    }
 
-        let render_comp = RenderComponent::new(0, 1, (0, 0), get_matrix((0, 0), (dimensions.0, dimensions.1), 1), 2);
-        self.render_components.set(entity, render_comp);
+    fn tick(&mut self) -> bool {
+        self.dirty = false;
+
+        self.run_stage(Stage::PreUpdate);
+        self.run_stage(Stage::Update);
+        self.run_stage(Stage::PostUpdate);
+
+        self.dirty || self.has_pending_moves()
+    }
+
+    fn draw(&mut self) -> Result<()> {
+        self.run_stage(Stage::Render);
+        self.run_stage(Stage::Debug);
+        self.renderer.render()
     }
 }
 
@@ -572,11 +1446,409 @@ fn insert_matrix(buffer: &mut Buffer, location: (u16, u16), matrix: &Buffer) {
 
     for (x, col) in matrix.iter().enumerate() {
         for (y, value) in col.iter().enumerate() {
-            buffer[column + x][row + y] = *value;
+            // clipped at the edge of the buffer
+            if let Some(cell) = buffer.get_mut(column + x).and_then(|c| c.get_mut(row + y)) {
+                *cell = *value;
+            }
         }
     }
 }
 
-fn is_event_availble() -> Result<bool> {
-    poll(Duration::from_secs(0))
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::HeadlessBackend;
+    use rand::RngCore;
+
+    fn test_game() -> GameState {
+        GameState::new_headless((200, 100), (150, 60), style_map())
+    }
+
+    // a few turns in, with a dead enemy so the free list isn't empty
+    fn played_game() -> GameState {
+        let mut game = test_game();
+        game.rng = GameRng::new(0);
+        game.init_player((5, 5));
+        game.init_test_enemy((22, 18), NORMAL_SPEED);
+        game.init_test_seeker((60, 40), (5, 5));
+        game.init_test_coward((30, 30));
+        game.init_door((8, 5));
+        game.init_crate((5, 8));
+        game.init_map(0.45);
+        game.log.info("Saved with a space, a \\ and a \"quote\"");
+
+        for _ in 0..10 {
+            if let Some(player) = game.player {
+                if game.is_player_turn() {
+                    game.move_entity(player, Direction::Right);
+                    game.end_player_turn(MOVE_COST);
+                }
+            }
+            game.tick();
+        }
+        let enemy = game.world.borrow::<EnemyAIComponent>().iter().next().map(|(e, _)| e).unwrap();
+        game.despawn(enemy);
+
+        game
+    }
+
+    fn save_load(game: &GameState) -> GameState {
+        let mut out = SaveWriter::new();
+        game.save(&mut out);
+
+        let mut loaded = test_game();
+        loaded.load(&mut SaveReader::new(&out.finish()).unwrap()).unwrap();
+        loaded
+    }
+
+    fn assert_same_storage<T: PartialEq + std::fmt::Debug + 'static>(a: &GameState, b: &GameState) {
+        assert_eq!(*a.world.borrow::<T>(), *b.world.borrow::<T>(), "{}", std::any::type_name::<T>());
+    }
+
+    #[test]
+    fn save_and_load_keep_the_world() {
+        let game = played_game();
+        let loaded = save_load(&game);
+
+        assert_eq!(game.world.entity_allocator, loaded.world.entity_allocator);
+        assert_same_storage::<RenderComponent>(&game, &loaded);
+        assert_same_storage::<MovementComponent>(&game, &loaded);
+        assert_same_storage::<CollisionComponent>(&game, &loaded);
+        assert_same_storage::<EnergyComponent>(&game, &loaded);
+        assert_same_storage::<EnemyAIComponent>(&game, &loaded);
+        assert_same_storage::<DoorComponent>(&game, &loaded);
+        assert_same_storage::<PushableComponent>(&game, &loaded);
+        assert_same_storage::<HealthComponent>(&game, &loaded);
+        assert_same_storage::<AttackComponent>(&game, &loaded);
+        assert_same_storage::<DefenseComponent>(&game, &loaded);
+        assert_same_storage::<FactionComponent>(&game, &loaded);
+    }
+
+    #[test]
+    fn save_and_load_keep_the_resources() {
+        let game = played_game();
+        let loaded = save_load(&game);
+
+        assert_eq!(game.tilemap, loaded.tilemap);
+        assert_eq!(game.fov, loaded.fov);
+        assert_eq!(game.turn_scheduler, loaded.turn_scheduler);
+        assert_eq!(game.log, loaded.log);
+        assert_eq!(game.player, loaded.player);
+        assert_eq!(game.movement, loaded.movement);
+        assert_eq!(game.rng, loaded.rng);
+        assert_eq!(game.renderer.camera.offset, loaded.renderer.camera.offset);
+    }
+
+    // same rolls after loading as the game would have made anyway
+    #[test]
+    fn loaded_rng_carries_on() {
+        let mut game = played_game();
+        let mut loaded = save_load(&game);
+
+        assert_eq!(game.rng.next_u64(), loaded.rng.next_u64());
+    }
+
+    // A recorded game played back has to end where it did when it was
+    // recorded. When a change means to alter play, record it again with
+    // --record --seed 7 and update the numbers
+    #[test]
+    fn replay_ends_where_the_recording_did() {
+        let recording = Recording::parse(include_str!("../replays/walk.txt")).unwrap();
+        let mut game = test_game();
+        game.rng = GameRng::new(recording.seed);
+        game.init_test_level(true);
+
+        let mut game_loop = GameLoop::new(LoopConfig::new(20, 60, LoopMode::RealTime));
+        game_loop.replay(&mut game, &recording).unwrap();
+
+        assert_eq!(game_loop.ticks, recording.end_tick);
+        let player = game.player.expect("player died in the replay");
+        assert_eq!(game.world.get::<RenderComponent>(player).unwrap().position_tl, (20, 13));
+        assert_eq!(game.world.get::<HealthComponent>(player).unwrap().current, 20);
+        assert_eq!(game.world.borrow::<RenderComponent>().iter().count(), 7); // one enemy killed
+        assert_eq!(game.turn_scheduler.time, 520);
+    }
+
+    #[test]
+    fn old_save_versions_are_refused() {
+        let text = format!("cursive_ideas_save {} #world", SAVE_VERSION - 1);
+
+        assert!(SaveReader::new(&text).is_err());
+    }
+
+    #[test]
+    fn hud_says_when_the_log_is_scrolled() {
+        let mut game = test_game();
+        let bottom = game.renderer.ui.height - 1;
+        let hud_text = |game: &GameState| -> String {
+            (0..3).filter_map(|x| game.renderer.ui.get((x, bottom)).map(|c| *c.content())).collect()
+        };
+
+        game.log.info("one");
+        game.log.info("two");
+        game.draw_hud();
+        assert_eq!(hud_text(&game), "");
+
+        game.log.scroll_up(1);
+        game.draw_hud();
+        assert_eq!(hud_text(&game), "Log");
+    }
+
+    // open floor with nothing on it, fog of war off
+    fn open_game() -> GameState {
+        let mut game = test_game();
+        game.init_background();
+        game.fov.enabled = false;
+        game
+    }
+
+    fn glyph_at(game: &GameState, position: (u16, u16)) -> u8 {
+        game.renderer.input_buffer[position.0 as usize][position.1 as usize]
+    }
+
+    fn first_enemy(game: &GameState) -> Entity {
+        game.world.borrow::<EnemyAIComponent>().iter().next().map(|(e, _)| e).unwrap()
+    }
+
+    // the camera was never near where it stepped, so nothing was drawn there
+    #[test]
+    fn entities_moving_off_camera_keep_the_floor_under_them() {
+        let mut game = open_game();
+        game.init_player((5, 5));
+        game.init_test_enemy((160, 5), NORMAL_SPEED);
+        game.handle_render();
+
+        let enemy = first_enemy(&game);
+        game.world.get_mut::<MovementComponent>(enemy).unwrap().desired_position = (161, 5);
+        game.handle_collision();
+        game.handle_movement();
+        assert_eq!(game.world.get::<RenderComponent>(enemy).unwrap().position_tl, (161, 5));
+
+        // the player walks over and the camera follows
+        let player = game.player.unwrap();
+        game.world.get_mut::<RenderComponent>(player).unwrap().position_tl = (150, 5);
+        game.handle_render();
+
+        assert!(game.renderer.camera.offset.0 > 0);
+        assert_eq!(glyph_at(&game, (161, 5)), 4); // '*' on floor, not '#'
+    }
+
+    // the cell it steps onto is still dark on screen, it has
+    // to be drawn over the floor once the player can see it
+    #[test]
+    fn entities_walking_into_view_keep_the_floor_under_them() {
+        let mut game = open_game();
+        game.fov.enabled = true;
+        game.init_player((5, 5));
+        game.init_test_enemy((37, 5), NORMAL_SPEED);
+        game.handle_fov();
+        game.handle_render();
+        assert_eq!(glyph_at(&game, (36, 5)), UNSEEN_GLYPH);
+
+        let enemy = first_enemy(&game);
+        game.world.get_mut::<MovementComponent>(enemy).unwrap().desired_position = (36, 5);
+        game.handle_collision();
+        game.handle_movement();
+
+        let player = game.player.unwrap();
+        game.world.get_mut::<RenderComponent>(player).unwrap().position_tl = (6, 5);
+        game.handle_fov();
+        game.handle_render();
+
+        assert!(game.fov.is_visible((36, 5)));
+        assert_eq!(glyph_at(&game, (36, 5)), 4); // '*' on floor, not '%'
+    }
+
+    fn walk(game: &mut GameState, direction: Direction, steps: usize) {
+        for _ in 0..steps {
+            game.handle_input(InputAction::Move(direction));
+            game.tick();
+            game.draw().unwrap();
+        }
+    }
+
+    // the crate and the open door are on screen where the player steps,
+    // the player still has to be drawn over the floor
+    #[test]
+    fn pushing_the_crate_and_walking_through_the_door() {
+        let mut game = open_game();
+        game.init_player((5, 5));
+        game.init_door((8, 5));
+        game.init_crate((5, 8));
+        let player = game.player.unwrap();
+        let position = |game: &GameState| game.world.get::<RenderComponent>(player).unwrap().position_tl;
+        game.draw().unwrap();
+
+        walk(&mut game, Direction::Down, 3);
+        assert_eq!(position(&game), (5, 8));
+        assert_eq!(glyph_at(&game, (5, 8)), 2); // '@'
+        assert_eq!(glyph_at(&game, (5, 9)), 39); // '%'
+
+        // the first bump opens it, the second walks in
+        walk(&mut game, Direction::Up, 3);
+        walk(&mut game, Direction::Right, 4);
+        assert_eq!(position(&game), (8, 5));
+        assert_eq!(glyph_at(&game, (8, 5)), 2);
+
+        walk(&mut game, Direction::Right, 1);
+        assert_eq!(glyph_at(&game, (8, 5)), 38); // the open door again
+        assert_eq!(glyph_at(&game, (9, 5)), 2);
+    }
+
+    // a whole tick and frame, checked on the headless screen
+    #[test]
+    fn moved_player_is_drawn_over_the_floor() {
+        let mut game = open_game();
+        game.init_player((5, 5));
+        game.init_test_enemy((30, 5), NORMAL_SPEED);
+        game.draw().unwrap();
+
+        // enemies pick a move on their turn and make it next tick
+        walk(&mut game, Direction::Right, 2);
+
+        let screen = game.renderer.backend::<HeadlessBackend>().unwrap();
+        assert_eq!(screen.cell((7, 5)), Some('@'.on(Color::Grey)));
+        assert_eq!(screen.cell((5, 5)), Some(' '.on(Color::Grey)));
+        let enemy = first_enemy(&game);
+        let enemy_position = game.world.get::<RenderComponent>(enemy).unwrap().position_tl;
+        assert_ne!(enemy_position, (30, 5));
+        assert_eq!(screen.cell(enemy_position), Some('*'.on(Color::Grey)));
+    }
+
+    #[test]
+    fn diagonal_moves_only_when_turned_on() {
+        let mut game = open_game();
+        game.init_player((5, 5));
+        let player = game.player.unwrap();
+        let position = |game: &GameState| game.world.get::<RenderComponent>(player).unwrap().position_tl;
+
+        // refused without spending the turn
+        walk(&mut game, Direction::DownRight, 1);
+        assert_eq!(position(&game), (5, 5));
+        assert!(game.is_player_turn());
+
+        game.set_movement(Connectivity::Eight);
+        walk(&mut game, Direction::DownRight, 1);
+        assert_eq!(position(&game), (6, 6));
+        assert_eq!(game.player_dijk.connectivity, Connectivity::Eight);
+    }
+
+    // every edge and corner of the map, pushing off it goes nowhere
+    #[test]
+    fn walking_off_the_map_is_refused() {
+        let (right, bottom) = (199, 99);
+        let edges = [
+            ((0, 50), vec![Direction::Left]),
+            ((right, 50), vec![Direction::Right]),
+            ((100, 0), vec![Direction::Up]),
+            ((100, bottom), vec![Direction::Down]),
+            ((0, 0), vec![Direction::Up, Direction::Left, Direction::UpLeft, Direction::UpRight, Direction::DownLeft]),
+            ((right, 0), vec![Direction::Up, Direction::Right, Direction::UpRight, Direction::UpLeft, Direction::DownRight]),
+            ((0, bottom), vec![Direction::Down, Direction::Left, Direction::DownLeft, Direction::DownRight, Direction::UpLeft]),
+            ((right, bottom), vec![Direction::Down, Direction::Right, Direction::DownRight, Direction::DownLeft, Direction::UpRight]),
+        ];
+
+        for (start, directions) in edges.iter() {
+            let mut game = open_game();
+            game.set_movement(Connectivity::Eight);
+            game.init_player(*start);
+            let player = game.player.unwrap();
+
+            for direction in directions {
+                walk(&mut game, *direction, 1);
+                assert_eq!(game.world.get::<RenderComponent>(player).unwrap().position_tl, *start, "{:?} from {:?}", direction, start);
+            }
+        }
+    }
+
+    // the player bumps an enemy standing to the right
+    fn fight() -> (GameState, Entity) {
+        let mut game = open_game();
+        game.init_player((5, 5));
+        game.init_test_enemy((6, 5), NORMAL_SPEED / 2);
+        let enemy = first_enemy(&game);
+
+        (game, enemy)
+    }
+
+    #[test]
+    fn bumping_an_enemy_hits_it() {
+        let (mut game, enemy) = fight();
+        walk(&mut game, Direction::Right, 1);
+
+        // 5 attack and the enemy has no armour, nobody moved
+        assert_eq!(game.world.get::<HealthComponent>(enemy).unwrap().current, 5);
+        assert_eq!(game.world.get::<RenderComponent>(game.player.unwrap()).unwrap().position_tl, (5, 5));
+        assert_eq!(game.log.visible(1)[0].text, "You hit it for 5 damage.");
+    }
+
+    #[test]
+    fn dead_enemies_leave_the_world_and_the_scheduler() {
+        let (mut game, enemy) = fight();
+        assert!(game.turn_scheduler.is_scheduled(enemy));
+
+        walk(&mut game, Direction::Right, 2);
+
+        assert!(!game.world.is_live(enemy));
+        assert!(game.world.get::<HealthComponent>(enemy).is_none());
+        assert!(!game.turn_scheduler.is_scheduled(enemy));
+        // the way is clear now
+        walk(&mut game, Direction::Right, 1);
+        assert_eq!(game.world.get::<RenderComponent>(game.player.unwrap()).unwrap().position_tl, (6, 5));
+    }
+
+    fn load_text(text: &str) -> std::io::Result<()> {
+        let mut loaded = test_game();
+        loaded.load(&mut SaveReader::new(text)?)
+    }
+
+    fn saved_text(game: &GameState) -> String {
+        let mut out = SaveWriter::new();
+        game.save(&mut out);
+        out.finish()
+    }
+
+    #[test]
+    fn truncated_saves_are_refused() {
+        let text = saved_text(&played_game());
+        let tokens: Vec<&str> = text.split_whitespace().collect();
+
+        for cut in [tokens.len() / 4, tokens.len() / 2, tokens.len() - 1].iter() {
+            assert!(load_text(&tokens[..*cut].join(" ")).is_err(), "cut at {}", cut);
+        }
+    }
+
+    // these read fine, but would panic once drawn
+    #[test]
+    fn saves_that_dont_fit_the_map_are_refused() {
+        assert!(load_text(&saved_text(&played_game())).is_ok());
+
+        let mut game = played_game();
+        game.fov = FieldOfView::new((20, 10), 30);
+        assert!(load_text(&saved_text(&game)).is_err());
+
+        let mut game = played_game();
+        let player = game.player.unwrap();
+        game.world.get_mut::<RenderComponent>(player).unwrap().position_tl = (200, 5);
+        assert!(load_text(&saved_text(&game)).is_err());
+
+        let mut game = played_game();
+        game.renderer.camera.offset = (500, 0);
+        assert!(load_text(&saved_text(&game)).is_err());
+    }
+
+    // a field of view whose rows don't match the size it claims
+    #[test]
+    fn mismatched_field_of_view_is_refused() {
+        let fov = FieldOfView::new((200, 100), 30);
+        let mut out = SaveWriter::new();
+        out.write(&fov);
+        let fov_text = out.finish();
+        // same field of view, claiming to be one column wider
+        let broken = fov_text.replacen(" 200 100 200 ", " 201 100 200 ", 1);
+        assert_ne!(fov_text, broken);
+
+        let mut input = SaveReader::new(&broken).unwrap();
+        assert!(input.read::<FieldOfView>().is_err());
+    }
+}
@@ -1,6 +1,7 @@
 use rand::prelude::*;
 use rand::seq::SliceRandom;
-use crate::components::Direction;
+use crate::rng::GameRng;
+use crate::components::{Connectivity, Direction};
 use std::collections::HashMap;
 use std::collections::VecDeque;
 
@@ -20,9 +21,7 @@ impl CaveMapGenerator {
         }
     }
 
-    pub fn randomize(&mut self, fill_percent: f64) {
-        let mut rng = thread_rng();
-
+    pub fn randomize(&mut self, fill_percent: f64, rng: &mut GameRng) {
         for x in 0..self.dimensions.0 {
             for y in 0..self.dimensions.1 {
                 let num: f64 = rng.gen();
@@ -282,7 +281,7 @@ impl WaveFormCollapser {
             let mut rule: AdjanencyRule = AdjanencyRule::new(Vec::new());
 
             for (ib, b) in tiles.clone().iter().enumerate() {
-                for (dir_i, dir) in vec![Direction::Left, Direction::Up, Direction::Down, Direction::Right].iter().enumerate() {
+                for (dir_i, dir) in Connectivity::Four.directions().iter().enumerate() {
                     if compatible(a, b, dir) {
                         rule.allow(four_dir[dir_i], ib);
                     }
@@ -301,15 +300,14 @@ impl WaveFormCollapser {
         self.tiles = tiles.len();
     }
 
-    pub fn wfc_core(&mut self, output_size: (usize, usize)) {
+    pub fn wfc_core(&mut self, output_size: (usize, usize), rng: &mut GameRng) {
         self.dimensions = output_size;
-        let mut rng = thread_rng();
         // Left, Up, Down, Right
         let four_dir: Vec<(usize, usize)> = vec![(0, 1), (1, 0), (1, 2), (2, 1)];
 
         self.in_progress_map = vec![vec![(0..self.tiles).collect(); output_size.1]; output_size.0];
 
-        let mut start_coord: (usize, usize) = (rng.gen_range(0..output_size.0) as usize, rng.gen_range(0..output_size.1) as usize);
+        let mut start_coord: (usize, usize) = (rng.gen_range(0..output_size.0), rng.gen_range(0..output_size.1));
         let mut first_tile: usize = rng.gen_range(0..self.tiles);
 
         // for testing
@@ -395,12 +393,12 @@ impl WaveFormCollapser {
             } else {
                 //println!("\n---------\nEMPTY QUEUE\nRE-PICK\n---------");
 
-                let new_pick = match find_lowest_non_one_pos(&self.in_progress_map) { // slow
+                let new_pick = match find_lowest_non_one_pos(&self.in_progress_map, rng) { // slow
                     Some(pos) => pos,
                     None => break,
                 };
 
-                self.in_progress_map[new_pick.0][new_pick.1] = vec![*self.in_progress_map[new_pick.0][new_pick.1].choose(&mut rng).unwrap()];
+                self.in_progress_map[new_pick.0][new_pick.1] = vec![*self.in_progress_map[new_pick.0][new_pick.1].choose(rng).unwrap()];
                 self.queue.push_back(new_pick);
 
                 //println!("---------\nNEW START: {:?}\n---------\n", new_pick);                
@@ -422,9 +420,7 @@ impl WaveFormCollapser {
 }
 
 // General Ultility
-pub fn find_lowest_non_one_pos(map: &Vec<Vec<Vec<usize>>>) -> Option<(usize, usize)> {
-    let mut rng = thread_rng();
-    
+pub fn find_lowest_non_one_pos(map: &[Vec<Vec<usize>>], rng: &mut GameRng) -> Option<(usize, usize)> {
     let mut lowest_value: usize = 10000;
     let mut lowest_positions: Vec<(usize, usize)> = Vec::new();
     
@@ -447,7 +443,7 @@ pub fn find_lowest_non_one_pos(map: &Vec<Vec<Vec<usize>>>) -> Option<(usize, usi
         }
     }
 
-    let lowest_pos = lowest_positions.choose(&mut rng).unwrap() ;
+    let lowest_pos = lowest_positions.choose(rng).unwrap() ;
 
     if lowest_value == 10000 {
         return None;
@@ -530,6 +526,7 @@ fn compatible(a: &[[u8; 3]; 3], b: &[[u8; 3]; 3], direction: &Direction) -> bool
 
             return cross_a == cross_b;
         },
+        _ => false,
     }
 }
 
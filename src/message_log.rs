@@ -0,0 +1,220 @@
+use std::collections::VecDeque;
+use std::io::Result;
+
+use crossterm::style::{Color, Stylize};
+
+use crate::renderer::TextLine;
+use crate::save::{Persist, SaveReader, SaveWriter};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Message {
+    pub text: String,
+    pub color: Color,
+    pub count: u32, // same message in a row, shown as (x3)
+}
+
+impl Message {
+    pub fn new(text: &str, color: Color) -> Message {
+        Message {
+            text: text.to_string(),
+            color,
+            count: 1,
+        }
+    }
+
+    pub fn display(&self) -> String {
+        match self.count {
+            1 => self.text.clone(),
+            count => format!("{} (x{})", self.text, count),
+        }
+    }
+
+    pub fn to_line(&self) -> TextLine {
+        self.display()
+            .chars()
+            .map(|c| c.with(self.color).on(Color::Black))
+            .collect()
+    }
+}
+
+// Everything the game has told the player, newest last.
+// Any system can push to it, the panel shows the end of it
+#[derive(Debug, PartialEq)]
+pub struct MessageLog {
+    messages: VecDeque<Message>,
+    capacity: usize,
+    scroll: usize, // how many lines back from the newest
+}
+
+impl MessageLog {
+    pub fn new(capacity: usize) -> MessageLog {
+        MessageLog {
+            messages: VecDeque::new(),
+            capacity: capacity.max(1),
+            scroll: 0,
+        }
+    }
+
+    // repeats fold into the last line instead of filling the log
+    pub fn push(&mut self, text: &str, color: Color) {
+        match self.messages.back_mut() {
+            Some(last) if last.text == text && last.color == color => last.count += 1,
+            _ => self.messages.push_back(Message::new(text, color)),
+        }
+
+        while self.messages.len() > self.capacity {
+            self.messages.pop_front();
+        }
+
+        // something new, jump back to it
+        self.scroll = 0;
+    }
+
+    pub fn info(&mut self, text: &str) {
+        self.push(text, Color::White);
+    }
+
+    pub fn warn(&mut self, text: &str) {
+        self.push(text, Color::Yellow);
+    }
+
+    pub fn danger(&mut self, text: &str) {
+        self.push(text, Color::Red);
+    }
+
+    pub fn scroll_up(&mut self, lines: usize) {
+        self.scroll = (self.scroll + lines).min(self.messages.len().saturating_sub(1));
+    }
+
+    pub fn scroll_down(&mut self, lines: usize) {
+        self.scroll = self.scroll.saturating_sub(lines);
+    }
+
+    pub fn is_scrolled(&self) -> bool {
+        self.scroll > 0
+    }
+
+    // the rows lines ending at the scroll position, oldest first
+    pub fn visible(&self, rows: usize) -> Vec<&Message> {
+        let end = self.messages.len() - self.scroll.min(self.messages.len());
+        let start = end.saturating_sub(rows);
+
+        self.messages.range(start..end).collect()
+    }
+}
+
+impl Persist for Message {
+    fn save(&self, out: &mut SaveWriter) {
+        out.write(&self.text);
+        out.write(&self.color);
+        out.write(&self.count);
+    }
+
+    fn load(input: &mut SaveReader) -> Result<Self> {
+        Ok(Message {
+            text: input.read()?,
+            color: input.read()?,
+            count: input.read()?,
+        })
+    }
+}
+
+// scroll position is left behind, a loaded game starts at the newest
+impl Persist for MessageLog {
+    fn save(&self, out: &mut SaveWriter) {
+        out.write(&self.messages);
+        out.write(&self.capacity);
+    }
+
+    fn load(input: &mut SaveReader) -> Result<Self> {
+        Ok(MessageLog {
+            messages: input.read()?,
+            capacity: input.read()?,
+            scroll: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(log: &MessageLog, rows: usize) -> Vec<String> {
+        log.visible(rows).iter().map(|message| message.display()).collect()
+    }
+
+    #[test]
+    fn repeats_fold_into_one_line() {
+        let mut log = MessageLog::new(10);
+        log.info("You hit it.");
+        log.info("You hit it.");
+        log.info("You hit it.");
+        log.warn("It dies.");
+        log.info("You hit it.");
+
+        assert_eq!(texts(&log, 10), vec!["You hit it. (x3)", "It dies.", "You hit it."]);
+    }
+
+    // same words in another colour is another message
+    #[test]
+    fn colour_counts_for_repeats() {
+        let mut log = MessageLog::new(10);
+        log.info("Careful.");
+        log.danger("Careful.");
+
+        assert_eq!(texts(&log, 10), vec!["Careful.", "Careful."]);
+    }
+
+    #[test]
+    fn oldest_go_past_the_capacity() {
+        let mut log = MessageLog::new(3);
+        for i in 0..5 {
+            log.info(&i.to_string());
+        }
+        // repeats don't take up room
+        log.info("4");
+
+        assert_eq!(texts(&log, 10), vec!["2", "3", "4 (x2)"]);
+    }
+
+    #[test]
+    fn scrolling_stops_at_the_ends() {
+        let mut log = MessageLog::new(10);
+        for i in 0..5 {
+            log.info(&i.to_string());
+        }
+
+        log.scroll_up(2);
+        assert!(log.is_scrolled());
+        assert_eq!(texts(&log, 2), vec!["1", "2"]);
+
+        // the oldest line stays in view
+        log.scroll_up(100);
+        assert_eq!(texts(&log, 2), vec!["0"]);
+
+        log.scroll_down(100);
+        assert!(!log.is_scrolled());
+        assert_eq!(texts(&log, 2), vec!["3", "4"]);
+    }
+
+    #[test]
+    fn new_messages_scroll_back_down() {
+        let mut log = MessageLog::new(10);
+        log.info("one");
+        log.info("two");
+        log.scroll_up(1);
+        log.info("three");
+
+        assert!(!log.is_scrolled());
+        assert_eq!(texts(&log, 1), vec!["three"]);
+    }
+
+    #[test]
+    fn empty_log_shows_nothing() {
+        let mut log = MessageLog::new(10);
+        log.scroll_up(3);
+
+        assert!(!log.is_scrolled());
+        assert!(log.visible(5).is_empty());
+    }
+}
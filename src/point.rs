@@ -0,0 +1,135 @@
+use crate::components::{Connectivity, Direction};
+
+// A map coordinate that can't wrap. Stepping off the top or left
+// gives None instead of u16::MAX, use within() for the other edges
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Point {
+    pub x: u16,
+    pub y: u16,
+}
+
+impl Point {
+    pub fn new(x: u16, y: u16) -> Point {
+        Point { x, y }
+    }
+
+    pub fn offset(&self, dx: i32, dy: i32) -> Option<Point> {
+        let x = self.x as i32 + dx;
+        let y = self.y as i32 + dy;
+
+        if x < 0 || y < 0 || x > u16::MAX as i32 || y > u16::MAX as i32 {
+            return None;
+        }
+
+        Some(Point::new(x as u16, y as u16))
+    }
+
+    pub fn step(&self, direction: Direction) -> Option<Point> {
+        let (dx, dy) = direction.offset();
+        self.offset(dx, dy)
+    }
+
+    pub fn within(&self, dimensions: (usize, usize)) -> bool {
+        (self.x as usize) < dimensions.0 && (self.y as usize) < dimensions.1
+    }
+
+    // step that also has to land on the map
+    pub fn step_within(&self, direction: Direction, dimensions: (usize, usize)) -> Option<Point> {
+        match self.step(direction) {
+            Some(point) if point.within(dimensions) => Some(point),
+            _ => None,
+        }
+    }
+
+    // only the ones on the map, in Connectivity::directions order
+    pub fn neighbours(&self, connectivity: Connectivity, dimensions: (usize, usize)) -> Vec<Point> {
+        connectivity
+            .directions()
+            .iter()
+            .filter_map(|direction| self.step_within(*direction, dimensions))
+            .collect()
+    }
+}
+
+impl From<(u16, u16)> for Point {
+    fn from(position: (u16, u16)) -> Point {
+        Point::new(position.0, position.1)
+    }
+}
+
+impl From<Point> for (u16, u16) {
+    fn from(point: Point) -> (u16, u16) {
+        (point.x, point.y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAP: (usize, usize) = (10, 5);
+
+    fn step(position: (u16, u16), direction: Direction) -> Option<(u16, u16)> {
+        Point::from(position).step_within(direction, MAP).map(|point| point.into())
+    }
+
+    #[test]
+    fn top_edge() {
+        assert_eq!(step((4, 0), Direction::Up), None);
+        assert_eq!(step((4, 0), Direction::Down), Some((4, 1)));
+    }
+
+    #[test]
+    fn bottom_edge() {
+        assert_eq!(step((4, 4), Direction::Down), None);
+        assert_eq!(step((4, 4), Direction::Up), Some((4, 3)));
+    }
+
+    #[test]
+    fn left_edge() {
+        assert_eq!(step((0, 2), Direction::Left), None);
+        assert_eq!(step((0, 2), Direction::Right), Some((1, 2)));
+    }
+
+    #[test]
+    fn right_edge() {
+        assert_eq!(step((9, 2), Direction::Right), None);
+        assert_eq!(step((9, 2), Direction::Left), Some((8, 2)));
+    }
+
+    #[test]
+    fn corners_with_diagonals() {
+        assert_eq!(step((0, 0), Direction::UpLeft), None);
+        assert_eq!(step((0, 0), Direction::UpRight), None);
+        assert_eq!(step((0, 0), Direction::DownLeft), None);
+        assert_eq!(step((0, 0), Direction::DownRight), Some((1, 1)));
+
+        assert_eq!(step((9, 0), Direction::UpRight), None);
+        assert_eq!(step((9, 0), Direction::DownLeft), Some((8, 1)));
+        assert_eq!(step((0, 4), Direction::DownLeft), None);
+        assert_eq!(step((0, 4), Direction::UpRight), Some((1, 3)));
+
+        assert_eq!(step((9, 4), Direction::DownRight), None);
+        assert_eq!(step((9, 4), Direction::UpRight), None);
+        assert_eq!(step((9, 4), Direction::DownLeft), None);
+        assert_eq!(step((9, 4), Direction::UpLeft), Some((8, 3)));
+    }
+
+    // only the ones on the map, a corner has three of eight
+    #[test]
+    fn corner_neighbours() {
+        assert_eq!(Point::new(0, 0).neighbours(Connectivity::Four, MAP).len(), 2);
+        assert_eq!(Point::new(9, 4).neighbours(Connectivity::Eight, MAP).len(), 3);
+        assert_eq!(Point::new(4, 2).neighbours(Connectivity::Eight, MAP).len(), 8);
+    }
+
+    // the ends of u16 itself, no map needed
+    #[test]
+    fn no_wrapping_at_the_u16_limits() {
+        assert_eq!(Point::new(0, 0).offset(-1, 0), None);
+        assert_eq!(Point::new(0, 0).offset(0, -1), None);
+        assert_eq!(Point::new(u16::MAX, 0).offset(1, 0), None);
+        assert_eq!(Point::new(0, u16::MAX).step(Direction::Down), None);
+        assert_eq!(Point::new(u16::MAX, u16::MAX).step(Direction::UpLeft), Some(Point::new(u16::MAX - 1, u16::MAX - 1)));
+    }
+}
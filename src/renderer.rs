@@ -1,18 +1,17 @@
-use std::io::{stdout};
-
 use crossterm::{
-    execute,
-    style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor,
-    StyledContent},
+    style::{Stylize, StyledContent},
     Result,
-    terminal::{EnterAlternateScreen, LeaveAlternateScreen, SetSize,
-    disable_raw_mode, enable_raw_mode, Clear, ClearType::{All}},
-    cursor::{MoveToColumn, MoveToRow, Hide, Show},
 };
 
+use crate::backend::{Backend, CrosstermBackend, HeadlessBackend};
+use crate::tilemap::TileMap;
+use crate::fov::{FieldOfView, Visibility, UNSEEN_GLYPH};
+use crate::ui::UiLayer;
+
 pub type Dimemsion = (u16, u16);
 pub type Buffer = Vec<Vec<u8>>;
 pub type StyleMap = Vec<StyledContent<char>>;
+pub type TextLine = Vec<StyledContent<char>>;
 
 fn get_sub_view(buffer: &Buffer, location: (u16, u16), width: usize, height: usize) -> Buffer {
     let (column, row) : (usize, usize) = (location.0.into(), location.1.into());
@@ -25,91 +24,260 @@ fn get_sub_view(buffer: &Buffer, location: (u16, u16), width: usize, height: usi
     return view;
 }
 
+// Decides which part of the input buffer ends up on screen
+pub struct Camera {
+    pub offset: Dimemsion,
+    pub target: Option<Dimemsion>,
+    pub dead_zone: Dimemsion, // how close the target can get to the edge before scrolling
+}
+
+impl Camera {
+    pub fn new(dead_zone: Dimemsion) -> Camera {
+        Camera {
+            offset: (0, 0),
+            target: None,
+            dead_zone,
+        }
+    }
+
+    // view and map are in cells, as (columns, rows)
+    pub fn follow(&mut self, target: Dimemsion, view: (usize, usize), map: (usize, usize)) {
+        self.target = Some(target);
+
+        let offset_x = follow_axis(self.offset.0 as usize, target.0 as usize, self.dead_zone.0 as usize, view.0, map.0);
+        let offset_y = follow_axis(self.offset.1 as usize, target.1 as usize, self.dead_zone.1 as usize, view.1, map.1);
+
+        self.offset = (offset_x as u16, offset_y as u16);
+    }
+}
+
+fn follow_axis(offset: usize, target: usize, dead_zone: usize, view: usize, map: usize) -> usize {
+    // dead zone can't be more than half the view or it would never settle
+    let dead_zone = dead_zone.min(view.saturating_sub(1) / 2);
+    let mut offset = offset;
+
+    if target < offset + dead_zone {
+        offset = target.saturating_sub(dead_zone);
+    } else if target + dead_zone + 1 > offset + view {
+        offset = target + dead_zone + 1 - view;
+    }
+
+    // clamp to map, so never show past the edges
+    offset.min(map.saturating_sub(view))
+}
+
+// What the last render cost
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameStats {
+    pub cells: usize,
+    pub runs: usize,
+    pub bytes: usize,
+}
+
 pub struct Renderer {
     pub input_buffer: Buffer,
-    pub render_buffer: Buffer,
+    pub render_buffer: Buffer, // what is currently on screen, view sized
     pub dimensions: Dimemsion,
     pub view_port: Dimemsion,
     pub style_map: StyleMap,
+    pub camera: Camera,
+    pub last_frame: FrameStats,
+    pub panel_rows: u16, // rows for text under the view port
+    panel: Vec<TextLine>,
+    drawn_panel: Vec<Option<TextLine>>, // what is currently on screen
+    pub ui: UiLayer, // drawn over the map, view sized
+    drawn_ui: UiLayer,
+    backend: Box<dyn Backend>,
 }
 
 impl Renderer {
     pub fn initialize(dimensions: Dimemsion, view_port: Dimemsion, style_map: StyleMap) -> Result<Renderer> {
-        enable_raw_mode()?;
-        execute!(
-            stdout(), EnterAlternateScreen, Hide,
-            SetBackgroundColor(Color::Black),
-            SetForegroundColor(Color::Black),
-        )?;
-        
-        execute!(
-            stdout(),
-            SetSize(view_port.0, view_port.1), // columns, rows
-            Clear(All),
-            MoveToColumn(0),
-            MoveToRow(0),
-        )?;
+        Renderer::with_backend(dimensions, view_port, style_map, Box::new(CrosstermBackend::new()))
+    }
 
-        let buffer = vec![vec![0; dimensions.1 as usize + 1]; dimensions.0 as usize + 1];
+    pub fn headless(dimensions: Dimemsion, view_port: Dimemsion, style_map: StyleMap) -> Result<Renderer> {
+        Renderer::with_backend(dimensions, view_port, style_map, Box::new(HeadlessBackend::new()))
+    }
+
+    pub fn with_backend(dimensions: Dimemsion, view_port: Dimemsion, style_map: StyleMap, mut backend: Box<dyn Backend>) -> Result<Renderer> {
+        backend.initialize(view_port)?;
 
-        Ok(Renderer{
-            input_buffer: buffer.clone(),
-            render_buffer: buffer.clone(),
+        let buffer = vec![vec![0; dimensions.1 as usize + 1]; dimensions.0 as usize + 1];
+        let mut renderer = Renderer {
+            input_buffer: buffer,
+            render_buffer: Vec::new(),
             dimensions,
             view_port,
             style_map,
-        })
+            camera: Camera::new((view_port.0 / 4, view_port.1 / 4)),
+            last_frame: FrameStats::default(),
+            panel_rows: 0,
+            panel: Vec::new(),
+            drawn_panel: Vec::new(),
+            ui: UiLayer::new(0, 0),
+            drawn_ui: UiLayer::new(0, 0),
+            backend,
+        };
+        let view_box = renderer.view_box();
+        renderer.render_buffer = vec![vec![0; view_box.1]; view_box.0];
+        renderer.ui = UiLayer::new(view_box.0, view_box.1);
+        renderer.drawn_ui = UiLayer::new(view_box.0, view_box.1);
+
+        Ok(renderer)
+
+    }
+
+    pub fn reset_term(&mut self) -> Result<()> {
+        self.backend.reset()
+    }
 
+    // get at the concrete backend, eg. the headless grid in tests
+    #[cfg(test)]
+    pub fn backend<B: Backend + 'static>(&self) -> Option<&B> {
+        self.backend.as_any().downcast_ref::<B>()
     }
 
-    pub fn reset_term() -> Result<()> {
-        disable_raw_mode()?;
-        execute!(stdout(), ResetColor, LeaveAlternateScreen, Show)?;
+    // size of the visible area, never bigger than the map itself
+    pub fn view_box(&self) -> (usize, usize) {
+        let width = (self.view_port.0 as usize + 1).min(self.dimensions.0 as usize);
+        let height = (self.view_port.1 as usize + 1).min(self.dimensions.1 as usize);
+
+        (width, height)
+    }
+
+    // Adds rows for text under the map, the terminal gets
+    // bigger rather than the map view smaller
+    pub fn set_panel_rows(&mut self, rows: u16) -> Result<()> {
+        self.panel_rows = rows;
+        self.panel = vec![Vec::new(); self.panel_rows as usize];
+        // the resize clears the screen, so everything gets drawn again
+        self.drawn_panel = vec![None; self.panel_rows as usize];
+        self.backend.resize((self.view_port.0, self.view_port.1 + rows))?;
+
+        let view_box = self.view_box();
+        self.render_buffer = vec![vec![u8::MAX; view_box.1]; view_box.0];
+        self.drawn_ui = UiLayer::new(view_box.0, view_box.1);
+
         Ok(())
     }
 
+    // Row 0 is the top of the panel, cut or padded to the width on render
+    pub fn set_panel_line(&mut self, row: usize, line: TextLine) {
+        if let Some(current) = self.panel.get_mut(row) {
+            *current = line;
+        }
+    }
+
+    pub fn follow(&mut self, target: Dimemsion) {
+        let view_box = self.view_box();
+        let map = (self.dimensions.0 as usize, self.dimensions.1 as usize);
+
+        self.camera.follow(target, view_box, map);
+    }
+
     pub fn render(&mut self) -> Result<()> {
         // find dimensions
-        let view_box: (usize, usize) = ((self.view_port.0 + 1).into(), (self.view_port.1 + 1).into());
+        let view_box = self.view_box();
+
+        // render buffer holds the last drawn view, so diffing against it
+        // still works after the camera has moved
+        let current_render: &Buffer = &self.render_buffer;
+        let modified: Buffer = get_sub_view(&self.input_buffer, self.camera.offset, view_box.0, view_box.1);
 
-        let current_render: Buffer = get_sub_view(&self.render_buffer, (0, 0), view_box.0, view_box.1);
-        let modified: Buffer = get_sub_view(&self.input_buffer, (0, 0), view_box.0, view_box.1);
+        let mut stats = FrameStats::default();
+        let mut run: Vec<StyledContent<char>> = Vec::new();
 
-        // find modified points and draw them
-        for (x, column) in current_render.iter().enumerate() {
-            for (y, row) in column.iter().enumerate() {
-                if *row == modified[x][y] {
+        // find modified points, go row by row so
+        // neighbouring changes can be drawn as one run
+        for y in 0..view_box.1 {
+            let mut run_start: usize = 0;
+
+            for x in 0..view_box.0 {
+                // ui goes over the map, a cell changes if either did
+                let ui_cell = self.ui.get((x, y));
+                let same = current_render[x][y] == modified[x][y] && self.drawn_ui.get((x, y)) == ui_cell;
+
+                if same {
+                    if !run.is_empty() {
+                        self.backend.draw_run((run_start as u16, y as u16), &run)?;
+                        stats.runs += 1;
+                        run.clear();
+                    }
                     continue;
-                } else {
-                    let character: StyledContent<char> = self.style_map[modified[x][y] as usize];
-                    execute!(
-                        stdout(),
-                        MoveToColumn(x as u16),
-                        MoveToRow(y as u16),
-                        Print(character)
-                    )?;
                 }
+
+                if run.is_empty() {
+                    run_start = x;
+                }
+                run.push(match ui_cell {
+                    Some(content) => content,
+                    None => self.style_map[modified[x][y] as usize],
+                });
+                stats.cells += 1;
+            }
+
+            if !run.is_empty() {
+                self.backend.draw_run((run_start as u16, y as u16), &run)?;
+                stats.runs += 1;
+                run.clear();
             }
         }
 
         // upadte render buffer, then different now the same
-        self.render_buffer = self.input_buffer.clone();
-        // reset cursor
-        execute!(
-            stdout(),
-            MoveToColumn(0),
-            MoveToRow(0)
-        )?;
+        self.render_buffer = modified;
+        self.drawn_ui = self.ui.clone();
+
+        // panel is small, a changed row is just drawn again whole
+        let width = self.view_port.0 as usize + 1;
+        for row in 0..self.panel.len() {
+            if self.drawn_panel[row].as_ref() == Some(&self.panel[row]) {
+                continue;
+            }
+
+            let mut line: TextLine = self.panel[row].iter().take(width).copied().collect();
+            line.resize(width, ' '.stylize());
+
+            self.backend.draw_run((0, (view_box.1 + row) as u16), &line)?;
+            stats.runs += 1;
+            stats.cells += width;
+            self.drawn_panel[row] = Some(self.panel[row].clone());
+        }
+        stats.bytes = self.backend.flush()?;
+        self.last_frame = stats;
 
         Ok(())
     }
 
+    // only the part the camera can see, the rest is never looked at
+    pub fn draw_tilemap(&mut self, tilemap: &TileMap, fov: &FieldOfView) {
+        let view_box = self.view_box();
+        let offset = self.camera.offset;
+
+        for x in 0..view_box.0 {
+            for y in 0..view_box.1 {
+                let position = (offset.0 + x as u16, offset.1 + y as u16);
+
+                self.input_buffer[position.0 as usize][position.1 as usize] = match tilemap.get(position) {
+                    Some(tile) => match fov.get(position) {
+                        Visibility::Visible => tile.glyph,
+                        Visibility::Remembered => tile.remembered_glyph,
+                        Visibility::Unseen => UNSEEN_GLYPH,
+                    },
+                    None => UNSEEN_GLYPH, // off the map, blank like the dark
+                };
+            }
+        }
+    }
+
+    // the game draws through draw_tilemap, these are for tests
+    #[cfg(test)]
     pub fn insert_char(&mut self, location: (u16, u16), value: u8) {
         let single_matrix = vec![vec![value]];
 
         self.insert_matrix(location, &single_matrix);
     }
 
+    #[cfg(test)]
     pub fn insert_matrix(&mut self, location: (u16, u16), matrix: &Buffer) {
         let (column, row) : (usize, usize) = (location.0.into(), location.1.into());
 
@@ -119,4 +287,122 @@ impl Renderer {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::HeadlessBackend;
+    use crossterm::cursor::MoveTo;
+    use crossterm::style::{Color, PrintStyledContent};
+
+    // 0 floor, 1 wall, 2 the player
+    fn test_renderer() -> Renderer {
+        let style_map: StyleMap = vec!['.'.with(Color::Grey), '#'.with(Color::White), '@'.with(Color::Yellow)];
+        Renderer::headless((8, 4), (7, 3), style_map).unwrap()
+    }
+
+    fn headless(renderer: &Renderer) -> &HeadlessBackend {
+        renderer.backend::<HeadlessBackend>().unwrap()
+    }
+
+    #[test]
+    fn known_map_ends_up_on_the_headless_screen() {
+        let mut renderer = test_renderer();
+        let room = vec![
+            vec![1, 1, 1, 1],
+            vec![1, 0, 0, 1],
+            vec![1, 0, 0, 1],
+            vec![1, 1, 1, 1],
+        ];
+        renderer.insert_matrix((0, 0), &room);
+        renderer.insert_char((1, 2), 2);
+        renderer.render().unwrap();
+
+        // floor is what the screen starts as, so it's never sent
+        let screen = headless(&renderer);
+        assert_eq!(screen.frames, 1);
+        assert_eq!(screen.row_text(0), "####    ");
+        assert_eq!(screen.row_text(1), "#  #    ");
+        assert_eq!(screen.row_text(2), "#@ #    ");
+        assert_eq!(screen.row_text(3), "####    ");
+        assert_eq!(screen.cell((1, 2)), Some('@'.with(Color::Yellow)));
+        assert_eq!(screen.cell((1, 1)), None);
+
+        // the player steps right, only those two cells get drawn again
+        renderer.insert_char((1, 2), 0);
+        renderer.insert_char((2, 2), 2);
+        renderer.render().unwrap();
+
+        assert_eq!(renderer.last_frame.cells, 2);
+        assert_eq!(headless(&renderer).row_text(2), "#.@#    ");
+    }
+
+    // what drawing every cell on its own with execute! used to write
+    fn per_cell_bytes(renderer: &Renderer) -> usize {
+        let mut bytes: Vec<u8> = Vec::new();
+        let view_box = renderer.view_box();
+
+        for x in 0..view_box.0 {
+            for y in 0..view_box.1 {
+                let glyph = renderer.input_buffer[x][y] as usize;
+                crossterm::queue!(bytes, MoveTo(x as u16, y as u16), PrintStyledContent(renderer.style_map[glyph])).unwrap();
+            }
+        }
+
+        bytes.len()
+    }
+
+    #[test]
+    fn full_redraw_is_cheaper_than_a_cell_at_a_time() {
+        let style_map: StyleMap = vec![' '.stylize(), '.'.with(Color::Grey), '#'.with(Color::White)];
+        let mut renderer = Renderer::headless((80, 24), (79, 23), style_map).unwrap();
+
+        // walls round a room, every cell differs from the empty screen
+        for x in 0..80 {
+            for y in 0..24 {
+                let wall = !(5..=70).contains(&x) || !(3..=20).contains(&y);
+                renderer.insert_char((x, y), if wall { 2 } else { 1 });
+            }
+        }
+        renderer.render().unwrap();
+
+        let stats = renderer.last_frame;
+        assert_eq!(stats.cells, 80 * 24);
+        assert!(stats.bytes * 5 < per_cell_bytes(&renderer), "{} against {}", stats.bytes, per_cell_bytes(&renderer));
+    }
+
+    #[test]
+    fn camera_stays_on_the_map() {
+        let mut renderer = Renderer::headless((20, 10), (7, 3), vec![' '.stylize(); 40]).unwrap();
+        renderer.follow((19, 9));
+
+        let view_box = renderer.view_box();
+        assert_eq!(renderer.camera.offset.0 as usize + view_box.0, 20);
+        assert_eq!(renderer.camera.offset.1 as usize + view_box.1, 10);
+    }
+
+    // a tilemap smaller than the renderer leaves blank cells, not a '^'
+    #[test]
+    fn off_map_cells_are_blank() {
+        let mut renderer = Renderer::headless((8, 4), (7, 3), vec![' '.stylize(); 40]).unwrap();
+        let tilemap = TileMap::new((4, 4));
+        let fov = FieldOfView::new((4, 4), 5);
+        renderer.draw_tilemap(&tilemap, &fov);
+
+        assert_eq!(renderer.input_buffer[6][1], UNSEEN_GLYPH);
+    }
+
+    #[test]
+    fn panel_goes_under_the_map() {
+        let mut renderer = test_renderer();
+        renderer.set_panel_rows(2).unwrap();
+        renderer.set_panel_line(1, "hi".chars().map(|c| c.stylize()).collect());
+        renderer.insert_char((0, 3), 1);
+        renderer.render().unwrap();
+
+        assert_eq!(renderer.view_box(), (8, 4));
+        assert_eq!(headless(&renderer).row_text(3), "#.......");
+        assert_eq!(headless(&renderer).row_text(5), "hi      ");
+    }
+}
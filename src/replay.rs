@@ -0,0 +1,133 @@
+use std::fs;
+use std::io::Result;
+
+use crate::input::InputAction;
+use crate::save::{Persist, SaveReader, SaveWriter};
+
+// Apart from saves, so changing the save layout doesn't break old
+// recordings. Bump when the recording layout itself changes
+const RECORDING_VERSION: u32 = 2;
+const RECORDING_MAGIC: &str = "cursive_ideas_recording";
+
+// Every action the player took and the tick it came in on, plus the
+// seed. Seed and actions are all a game is, so playing them back gives
+// the same game, crashes included, whatever the keymap
+pub struct Recording {
+    pub seed: u64,
+    pub inputs: Vec<(u64, InputAction)>,
+    pub end_tick: u64, // last tick seen, the crash may come after the last action
+}
+
+impl Recording {
+    pub fn new(seed: u64) -> Recording {
+        Recording {
+            seed,
+            inputs: Vec::new(),
+            end_tick: 0,
+        }
+    }
+
+    pub fn from_file(path: &str) -> Result<Recording> {
+        Recording::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Recording> {
+        let mut input = SaveReader::with_header(text, RECORDING_MAGIC, RECORDING_VERSION)?;
+        input.section("recording")?;
+        input.read()
+    }
+
+    pub fn to_file(&self, path: &str) -> Result<()> {
+        fs::write(path, self.finish())
+    }
+
+    pub fn finish(&self) -> String {
+        let mut out = SaveWriter::with_header(RECORDING_MAGIC, RECORDING_VERSION);
+        out.section("recording");
+        out.write(self);
+        out.finish()
+    }
+}
+
+// Fills a recording as the loop runs. Written out when dropped
+// so a panic still leaves the actions that led up to it behind
+pub struct Recorder {
+    pub recording: Recording,
+    path: String,
+}
+
+impl Recorder {
+    pub fn new(seed: u64, path: &str) -> Recorder {
+        Recorder {
+            recording: Recording::new(seed),
+            path: path.to_string(),
+        }
+    }
+
+    pub fn record(&mut self, tick: u64, action: InputAction) {
+        self.recording.inputs.push((tick, action));
+        self.recording.end_tick = tick;
+    }
+
+    pub fn set_tick(&mut self, tick: u64) {
+        self.recording.end_tick = tick;
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if let Err(err) = self.recording.to_file(&self.path) {
+            eprintln!("Could not write recording {}: {}", self.path, err);
+        }
+    }
+}
+
+impl Persist for Recording {
+    fn save(&self, out: &mut SaveWriter) {
+        out.write(&self.seed);
+        out.write(&self.inputs);
+        out.write(&self.end_tick);
+    }
+
+    fn load(input: &mut SaveReader) -> Result<Self> {
+        Ok(Recording {
+            seed: input.read()?,
+            inputs: input.read()?,
+            end_tick: input.read()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::Direction;
+    use crate::debug_overlay::DebugOverlay;
+
+    #[test]
+    fn recordings_round_trip() {
+        let mut recording = Recording::new(42);
+        recording.inputs.push((3, InputAction::Move(Direction::UpLeft)));
+        recording.inputs.push((9, InputAction::ToggleOverlay(DebugOverlay::Dijkstra)));
+        recording.end_tick = 20;
+
+        let loaded = Recording::parse(&recording.finish()).unwrap();
+        assert_eq!(loaded.seed, 42);
+        assert_eq!(loaded.inputs, recording.inputs);
+        assert_eq!(loaded.end_tick, 20);
+    }
+
+    // written by hand, so it stays readable whatever SAVE_VERSION becomes
+    #[test]
+    fn recordings_dont_follow_the_save_version() {
+        let text = "cursive_ideas_recording 2 #recording 7 1 4 \"move_right 12";
+        let recording = Recording::parse(text).unwrap();
+
+        assert_eq!(recording.inputs, vec![(4, InputAction::Move(Direction::Right))]);
+    }
+
+    #[test]
+    fn saves_are_not_recordings() {
+        assert!(Recording::parse(&SaveWriter::new().finish()).is_err());
+    }
+}
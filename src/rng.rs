@@ -0,0 +1,65 @@
+use std::io::Result;
+
+use rand::{Error, RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::save::{Persist, SaveReader, SaveWriter};
+
+// The one source of randomness for maps and AI. Same seed and
+// same input gives the same game, so a seed is enough for a bug report
+#[derive(Debug, PartialEq)]
+pub struct GameRng {
+    seed: u64,
+    rng: ChaCha8Rng, // not StdRng, its algorithm may change between versions
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> GameRng {
+        GameRng {
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
+    }
+
+    // a seed nobody picked, still printed so the game can be replayed
+    pub fn from_entropy() -> GameRng {
+        GameRng::new(rand::random())
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+impl RngCore for GameRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> std::result::Result<(), Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
+
+// seed and how far along it is, so a loaded game rolls the same
+impl Persist for GameRng {
+    fn save(&self, out: &mut SaveWriter) {
+        out.write(&self.seed);
+        out.write(&self.rng.get_word_pos());
+    }
+
+    fn load(input: &mut SaveReader) -> Result<Self> {
+        let mut rng = GameRng::new(input.read()?);
+        rng.rng.set_word_pos(input.read()?);
+
+        Ok(rng)
+    }
+}
@@ -0,0 +1,358 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::str::FromStr;
+
+use crossterm::style::Color;
+
+// Bump when the layout changes, old saves are refused rather than misread
+pub const SAVE_VERSION: u32 = 3;
+const SAVE_MAGIC: &str = "cursive_ideas_save";
+
+// Saves are plain text, a stream of whitespace separated tokens.
+// Every type writes its fields in order and reads them back in
+// the same order, section tags catch most mistakes early
+pub struct SaveWriter {
+    tokens: Vec<String>,
+}
+
+impl SaveWriter {
+    pub fn new() -> SaveWriter {
+        SaveWriter::with_header(SAVE_MAGIC, SAVE_VERSION)
+    }
+
+    // for other files in the same format, versioned on their own
+    pub fn with_header(magic: &str, version: u32) -> SaveWriter {
+        let mut writer = SaveWriter { tokens: Vec::new() };
+        writer.token(magic);
+        writer.token(&version.to_string());
+        writer
+    }
+
+    fn token(&mut self, token: &str) {
+        self.tokens.push(token.to_string());
+    }
+
+    pub fn section(&mut self, name: &str) {
+        self.token(&format!("#{}", name));
+    }
+
+    pub fn write<T: Persist>(&mut self, value: &T) {
+        value.save(self);
+    }
+
+    pub fn finish(&self) -> String {
+        let mut text = self.tokens.join(" ");
+        text.push('\n');
+        text
+    }
+
+    pub fn to_file(&self, path: &str) -> Result<()> {
+        fs::write(path, self.finish())
+    }
+}
+
+pub struct SaveReader {
+    tokens: Vec<String>,
+    position: usize,
+}
+
+impl SaveReader {
+    pub fn new(text: &str) -> Result<SaveReader> {
+        SaveReader::with_header(text, SAVE_MAGIC, SAVE_VERSION)
+    }
+
+    pub fn with_header(text: &str, magic: &str, version: u32) -> Result<SaveReader> {
+        let mut reader = SaveReader {
+            tokens: text.split_whitespace().map(|t| t.to_string()).collect(),
+            position: 0,
+        };
+
+        if reader.next()? != magic {
+            return Err(corrupt(&format!("not a {} file", magic)));
+        }
+        let found: u32 = reader.read()?;
+        if found != version {
+            return Err(corrupt(&format!("{} version {} but this build reads {}", magic, found, version)));
+        }
+
+        Ok(reader)
+    }
+
+    pub fn from_file(path: &str) -> Result<SaveReader> {
+        SaveReader::new(&fs::read_to_string(path)?)
+    }
+
+    fn next(&mut self) -> Result<&str> {
+        match self.tokens.get(self.position) {
+            Some(token) => {
+                self.position += 1;
+                Ok(token)
+            },
+            None => Err(corrupt("save ends early")),
+        }
+    }
+
+    pub fn section(&mut self, name: &str) -> Result<()> {
+        let token = self.next()?;
+
+        match token.strip_prefix('#') {
+            Some(found) if found == name => Ok(()),
+            _ => Err(corrupt(&format!("expected section {} but found {}", name, token))),
+        }
+    }
+
+    pub fn read<T: Persist>(&mut self) -> Result<T> {
+        T::load(self)
+    }
+
+    fn parse<T: FromStr>(&mut self) -> Result<T> {
+        let token = self.next()?;
+
+        match token.parse::<T>() {
+            Ok(value) => Ok(value),
+            Err(_) => Err(corrupt(&format!("could not read {}", token))),
+        }
+    }
+}
+
+pub fn corrupt(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+// Anything that goes in a save
+pub trait Persist: Sized {
+    fn save(&self, out: &mut SaveWriter);
+    fn load(input: &mut SaveReader) -> Result<Self>;
+}
+
+macro_rules! persist_parsed {
+    ($($t:ty),*) => {
+        $(
+            impl Persist for $t {
+                fn save(&self, out: &mut SaveWriter) {
+                    out.token(&self.to_string());
+                }
+
+                fn load(input: &mut SaveReader) -> Result<Self> {
+                    input.parse()
+                }
+            }
+        )*
+    };
+}
+
+// f32 prints the shortest form that reads back the same
+persist_parsed!(u8, u16, u32, u64, u128, usize, i32, f32, bool);
+
+impl Persist for String {
+    // Escaped so the text never has whitespace in it, the reader splits
+    // on all of it. The uncommon kinds go in as \u<hex>;, eg. \ua0; for
+    // a non breaking space
+    fn save(&self, out: &mut SaveWriter) {
+        let mut escaped = String::from("\"");
+        for c in self.chars() {
+            match c {
+                '\\' => escaped.push_str("\\\\"),
+                ' ' => escaped.push_str("\\s"),
+                '\n' => escaped.push_str("\\n"),
+                '\t' => escaped.push_str("\\t"),
+                c if c.is_whitespace() => escaped.push_str(&format!("\\u{:x};", c as u32)),
+                c => escaped.push(c),
+            }
+        }
+        out.token(&escaped);
+    }
+
+    fn load(input: &mut SaveReader) -> Result<Self> {
+        let token = input.next()?;
+        let escaped = match token.strip_prefix('"') {
+            Some(escaped) => escaped,
+            None => return Err(corrupt(&format!("expected a string but found {}", token))),
+        };
+
+        let mut text = String::new();
+        let mut chars = escaped.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                text.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('\\') => text.push('\\'),
+                Some('s') => text.push(' '),
+                Some('n') => text.push('\n'),
+                Some('t') => text.push('\t'),
+                Some('u') => {
+                    let hex: String = chars.by_ref().take_while(|c| *c != ';').collect();
+                    match u32::from_str_radix(&hex, 16).ok().and_then(std::char::from_u32) {
+                        Some(c) => text.push(c),
+                        None => return Err(corrupt("bad escape in string")),
+                    }
+                },
+                _ => return Err(corrupt("bad escape in string")),
+            }
+        }
+
+        Ok(text)
+    }
+}
+
+impl<A: Persist, B: Persist> Persist for (A, B) {
+    fn save(&self, out: &mut SaveWriter) {
+        self.0.save(out);
+        self.1.save(out);
+    }
+
+    fn load(input: &mut SaveReader) -> Result<Self> {
+        Ok((input.read()?, input.read()?))
+    }
+}
+
+impl<T: Persist> Persist for Option<T> {
+    fn save(&self, out: &mut SaveWriter) {
+        match self {
+            Some(value) => {
+                out.token("some");
+                value.save(out);
+            },
+            None => out.token("none"),
+        }
+    }
+
+    fn load(input: &mut SaveReader) -> Result<Self> {
+        match input.next()? {
+            "some" => Ok(Some(input.read()?)),
+            "none" => Ok(None),
+            token => Err(corrupt(&format!("expected an option but found {}", token))),
+        }
+    }
+}
+
+// length first
+impl<T: Persist> Persist for Vec<T> {
+    fn save(&self, out: &mut SaveWriter) {
+        self.len().save(out);
+        for value in self {
+            value.save(out);
+        }
+    }
+
+    fn load(input: &mut SaveReader) -> Result<Self> {
+        let len: usize = input.read()?;
+        let mut values = Vec::with_capacity(len.min(1 << 20));
+        for _ in 0..len {
+            values.push(input.read()?);
+        }
+        Ok(values)
+    }
+}
+
+impl<T: Persist> Persist for VecDeque<T> {
+    fn save(&self, out: &mut SaveWriter) {
+        self.len().save(out);
+        for value in self {
+            value.save(out);
+        }
+    }
+
+    fn load(input: &mut SaveReader) -> Result<Self> {
+        let values: Vec<T> = input.read()?;
+        Ok(values.into())
+    }
+}
+
+impl Persist for Color {
+    fn save(&self, out: &mut SaveWriter) {
+        let token = match self {
+            Color::Reset => "reset".to_string(),
+            Color::Black => "black".to_string(),
+            Color::DarkGrey => "dark_grey".to_string(),
+            Color::Red => "red".to_string(),
+            Color::DarkRed => "dark_red".to_string(),
+            Color::Green => "green".to_string(),
+            Color::DarkGreen => "dark_green".to_string(),
+            Color::Yellow => "yellow".to_string(),
+            Color::DarkYellow => "dark_yellow".to_string(),
+            Color::Blue => "blue".to_string(),
+            Color::DarkBlue => "dark_blue".to_string(),
+            Color::Magenta => "magenta".to_string(),
+            Color::DarkMagenta => "dark_magenta".to_string(),
+            Color::Cyan => "cyan".to_string(),
+            Color::DarkCyan => "dark_cyan".to_string(),
+            Color::White => "white".to_string(),
+            Color::Grey => "grey".to_string(),
+            Color::Rgb { r, g, b } => format!("rgb:{}:{}:{}", r, g, b),
+            Color::AnsiValue(value) => format!("ansi:{}", value),
+        };
+        out.token(&token);
+    }
+
+    fn load(input: &mut SaveReader) -> Result<Self> {
+        let token = input.next()?.to_string();
+        let parts: Vec<&str> = token.split(':').collect();
+        let number = |i: usize| -> Result<u8> {
+            match parts.get(i).map(|p| p.parse::<u8>()) {
+                Some(Ok(value)) => Ok(value),
+                _ => Err(corrupt(&format!("bad colour {}", token))),
+            }
+        };
+
+        match parts[0] {
+            "rgb" => Ok(Color::Rgb { r: number(1)?, g: number(2)?, b: number(3)? }),
+            "ansi" => Ok(Color::AnsiValue(number(1)?)),
+            "reset" => Ok(Color::Reset),
+            "black" => Ok(Color::Black),
+            "dark_grey" => Ok(Color::DarkGrey),
+            "red" => Ok(Color::Red),
+            "dark_red" => Ok(Color::DarkRed),
+            "green" => Ok(Color::Green),
+            "dark_green" => Ok(Color::DarkGreen),
+            "yellow" => Ok(Color::Yellow),
+            "dark_yellow" => Ok(Color::DarkYellow),
+            "blue" => Ok(Color::Blue),
+            "dark_blue" => Ok(Color::DarkBlue),
+            "magenta" => Ok(Color::Magenta),
+            "dark_magenta" => Ok(Color::DarkMagenta),
+            "cyan" => Ok(Color::Cyan),
+            "dark_cyan" => Ok(Color::DarkCyan),
+            "white" => Ok(Color::White),
+            "grey" => Ok(Color::Grey),
+            _ => Err(corrupt(&format!("bad colour {}", token))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip<T: Persist>(value: &T) -> T {
+        let mut out = SaveWriter::new();
+        out.write(value);
+        SaveReader::new(&out.finish()).unwrap().read().unwrap()
+    }
+
+    #[test]
+    fn strings_keep_every_kind_of_whitespace() {
+        let text = "a b\tc\nd\re\u{c}f\u{a0}g\u{2003}h\\i \"j\" ;".to_string();
+
+        assert_eq!(round_trip(&text), text);
+    }
+
+    #[test]
+    fn strings_dont_run_into_the_next_value() {
+        let mut out = SaveWriter::new();
+        out.write(&"one\rtwo\u{a0}".to_string());
+        out.write(&7u32);
+        let mut input = SaveReader::new(&out.finish()).unwrap();
+
+        assert_eq!(input.read::<String>().unwrap(), "one\rtwo\u{a0}");
+        assert_eq!(input.read::<u32>().unwrap(), 7);
+    }
+
+    #[test]
+    fn empty_strings_survive() {
+        assert_eq!(round_trip(&String::new()), "");
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::BinaryHeap;
+use std::cmp::Reverse;
+
+// Stages always run in this order, ordering constraints
+// only matter between systems in the same stage
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Stage {
+    PreUpdate,
+    Update,
+    PostUpdate,
+    Render,
+    Debug,
+}
+
+pub type System<T> = fn(&mut T);
+
+pub struct SystemEntry<T> {
+    pub name: &'static str,
+    pub stage: Stage,
+    pub enabled: bool,
+    run: System<T>,
+    before: Vec<&'static str>,
+    after: Vec<&'static str>,
+}
+
+impl<T> SystemEntry<T> {
+    pub fn before(&mut self, name: &'static str) -> &mut SystemEntry<T> {
+        self.before.push(name);
+        self
+    }
+
+    pub fn after(&mut self, name: &'static str) -> &mut SystemEntry<T> {
+        self.after.push(name);
+        self
+    }
+
+    pub fn disabled(&mut self) -> &mut SystemEntry<T> {
+        self.enabled = false;
+        self
+    }
+}
+
+pub struct Schedule<T> {
+    systems: Vec<SystemEntry<T>>,
+    // run order of every stage, None after a system was added
+    order: Option<Vec<Vec<usize>>>,
+}
+
+impl<T> Schedule<T> {
+    pub fn new() -> Schedule<T> {
+        Schedule {
+            systems: Vec::new(),
+            order: None,
+        }
+    }
+
+    pub fn add_system(&mut self, name: &'static str, stage: Stage, run: System<T>) -> &mut SystemEntry<T> {
+        if self.index_of(name).is_some() {
+            panic!("System {} added twice!", name);
+        }
+
+        // before and after can only be set on the entry handed back
+        // here, so adding is the one thing that changes the order
+        self.order = None;
+        self.systems.push(SystemEntry {
+            name,
+            stage,
+            enabled: true,
+            run,
+            before: Vec::new(),
+            after: Vec::new(),
+        });
+
+        self.systems.last_mut().unwrap()
+    }
+
+    // returns false if there is no system with that name
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        match self.index_of(name) {
+            Some(i) => {
+                self.systems[i].enabled = enabled;
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn toggle(&mut self, name: &str) -> bool {
+        let enabled = self.is_enabled(name);
+        self.set_enabled(name, !enabled);
+
+        !enabled
+    }
+
+    pub fn is_enabled(&self, name: &str) -> bool {
+        match self.index_of(name) {
+            Some(i) => self.systems[i].enabled,
+            None => false,
+        }
+    }
+
+    // How many systems a stage has, sorting them first if
+    // anything was added since the last time
+    pub fn stage_len(&mut self, stage: Stage) -> usize {
+        if self.order.is_none() {
+            let stages = [Stage::PreUpdate, Stage::Update, Stage::PostUpdate, Stage::Render, Stage::Debug];
+            self.order = Some(stages.iter().map(|stage| self.sort(*stage)).collect());
+        }
+
+        self.stage_order(stage).len()
+    }
+
+    // The i-th system of a stage in run order, None if it is disabled.
+    // Handed out as a plain fn, so the caller can still
+    // borrow the state that owns the schedule
+    pub fn system(&self, stage: Stage, i: usize) -> Option<System<T>> {
+        match self.stage_order(stage).get(i) {
+            Some(index) if self.systems[*index].enabled => Some(self.systems[*index].run),
+            _ => None,
+        }
+    }
+
+    fn stage_order(&self, stage: Stage) -> &[usize] {
+        match &self.order {
+            Some(order) => &order[stage as usize],
+            None => &[],
+        }
+    }
+
+    fn index_of(&self, name: &str) -> Option<usize> {
+        self.systems.iter().position(|s| s.name == name)
+    }
+
+    // Topological sort of one stage. Ties go to whichever was
+    // added first so the order never changes between runs
+    fn sort(&self, stage: Stage) -> Vec<usize> {
+        let members: Vec<usize> = (0..self.systems.len())
+            .filter(|i| self.systems[*i].stage == stage)
+            .collect();
+
+        // edges[a] has b if a runs before b
+        let mut edges: Vec<Vec<usize>> = vec![Vec::new(); self.systems.len()];
+        let mut incoming: Vec<usize> = vec![0; self.systems.len()];
+
+        for i in &members {
+            let system = &self.systems[*i];
+
+            for name in &system.before {
+                match self.index_of(name) {
+                    Some(other) if self.systems[other].stage == stage => {
+                        edges[*i].push(other);
+                        incoming[other] += 1;
+                    },
+                    _ => (), // other stage or missing, nothing to order
+                }
+            }
+            for name in &system.after {
+                match self.index_of(name) {
+                    Some(other) if self.systems[other].stage == stage => {
+                        edges[other].push(*i);
+                        incoming[*i] += 1;
+                    },
+                    _ => (),
+                }
+            }
+        }
+
+        let mut ready: BinaryHeap<Reverse<usize>> = members
+            .iter()
+            .filter(|i| incoming[**i] == 0)
+            .map(|i| Reverse(*i))
+            .collect();
+        let mut result: Vec<usize> = Vec::new();
+
+        while let Some(Reverse(i)) = ready.pop() {
+            result.push(i);
+
+            for next in &edges[i] {
+                incoming[*next] -= 1;
+                if incoming[*next] == 0 {
+                    ready.push(Reverse(*next));
+                }
+            }
+        }
+
+        if result.len() != members.len() {
+            let stuck: Vec<&str> = members
+                .iter()
+                .filter(|i| !result.contains(i))
+                .map(|i| self.systems[*i].name)
+                .collect();
+            panic!("Cycle in system ordering: {:?}", stuck);
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(schedule: &mut Schedule<Vec<&'static str>>, stage: Stage) -> Vec<&'static str> {
+        let mut ran = Vec::new();
+        for i in 0..schedule.stage_len(stage) {
+            if let Some(system) = schedule.system(stage, i) {
+                system(&mut ran);
+            }
+        }
+        ran
+    }
+
+    #[test]
+    fn order_follows_constraints_and_later_adds() {
+        let mut schedule: Schedule<Vec<&'static str>> = Schedule::new();
+        schedule.add_system("b", Stage::Update, |ran| ran.push("b")).after("a");
+        schedule.add_system("a", Stage::Update, |ran| ran.push("a"));
+        schedule.add_system("d", Stage::Update, |ran| ran.push("d")).disabled();
+        assert_eq!(run(&mut schedule, Stage::Update), vec!["a", "b"]);
+
+        // cached order is thrown out when something new comes in
+        schedule.add_system("c", Stage::Update, |ran| ran.push("c")).before("a");
+        schedule.set_enabled("d", true);
+        assert_eq!(run(&mut schedule, Stage::Update), vec!["d", "c", "a", "b"]);
+        assert!(run(&mut schedule, Stage::Debug).is_empty());
+    }
+}
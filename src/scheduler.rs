@@ -0,0 +1,239 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+use std::io::Result;
+
+use crate::generations::GenerationalIndex;
+use crate::save::{Persist, SaveReader, SaveWriter};
+
+// speed of a normal entity, twice this acts twice as often
+pub const NORMAL_SPEED: u32 = 100;
+
+// what things cost, in the same units the AI returns
+pub const MOVE_COST: u8 = 10;
+pub const WAIT_COST: u8 = 5;
+
+// how long an action keeps an entity busy
+pub fn action_delay(cost: u8, speed: u32) -> u64 {
+    (cost as u64 * NORMAL_SPEED as u64) / speed.max(1) as u64
+}
+
+#[derive(Clone, Copy, Debug)]
+struct ScheduledTurn {
+    time: u64,
+    order: u64, // ties go to whoever was scheduled first
+    entity: GenerationalIndex,
+}
+
+impl PartialEq for ScheduledTurn {
+    fn eq(&self, other: &Self) -> bool {
+        self.time == other.time && self.order == other.order
+    }
+}
+
+impl Eq for ScheduledTurn {}
+
+impl PartialOrd for ScheduledTurn {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ScheduledTurn {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.time, self.order).cmp(&(other.time, other.order))
+    }
+}
+
+// Priority queue of who acts next
+#[derive(Debug)]
+pub struct TurnScheduler {
+    queue: BinaryHeap<Reverse<ScheduledTurn>>,
+    pub time: u64,
+    order: u64,
+}
+
+impl TurnScheduler {
+    pub fn new() -> TurnScheduler {
+        TurnScheduler {
+            queue: BinaryHeap::new(),
+            time: 0,
+            order: 0,
+        }
+    }
+
+    // entity acts again delay after now
+    pub fn schedule(&mut self, entity: GenerationalIndex, delay: u64) {
+        self.queue.push(Reverse(ScheduledTurn {
+            time: self.time + delay,
+            order: self.order,
+            entity,
+        }));
+        self.order += 1;
+    }
+
+    pub fn peek(&self) -> Option<GenerationalIndex> {
+        self.queue.peek().map(|Reverse(turn)| turn.entity)
+    }
+
+    // take the next turn, moving time forward to it
+    pub fn pop(&mut self) -> Option<GenerationalIndex> {
+        match self.queue.pop() {
+            Some(Reverse(turn)) => {
+                self.time = turn.time;
+                Some(turn.entity)
+            },
+            None => None,
+        }
+    }
+
+    // drops every turn the entity has coming, eg. when it dies
+    pub fn remove(&mut self, entity: GenerationalIndex) {
+        self.queue.retain(|Reverse(turn)| turn.entity != entity);
+    }
+
+    #[cfg(test)]
+    pub fn is_scheduled(&self, entity: GenerationalIndex) -> bool {
+        self.queue.iter().any(|Reverse(turn)| turn.entity == entity)
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    // in the order they come up, the heap itself is in no useful order
+    fn turns(&self) -> Vec<ScheduledTurn> {
+        let mut turns: Vec<ScheduledTurn> = self.queue.iter().map(|Reverse(turn)| *turn).collect();
+        turns.sort();
+        turns
+    }
+}
+
+// same turns for the same entities, however the heap is laid out
+impl PartialEq for TurnScheduler {
+    fn eq(&self, other: &Self) -> bool {
+        let key = |turn: &ScheduledTurn| (turn.time, turn.order, turn.entity);
+
+        self.time == other.time
+            && self.order == other.order
+            && self.turns().iter().map(key).eq(other.turns().iter().map(key))
+    }
+}
+
+impl Persist for ScheduledTurn {
+    fn save(&self, out: &mut SaveWriter) {
+        out.write(&self.time);
+        out.write(&self.order);
+        out.write(&self.entity);
+    }
+
+    fn load(input: &mut SaveReader) -> Result<Self> {
+        Ok(ScheduledTurn {
+            time: input.read()?,
+            order: input.read()?,
+            entity: input.read()?,
+        })
+    }
+}
+
+// turns go out in the order they come up, so saves are stable
+impl Persist for TurnScheduler {
+    fn save(&self, out: &mut SaveWriter) {
+        out.write(&self.time);
+        out.write(&self.order);
+        out.write(&self.turns());
+    }
+
+    fn load(input: &mut SaveReader) -> Result<Self> {
+        let time = input.read()?;
+        let order = input.read()?;
+        let turns: Vec<ScheduledTurn> = input.read()?;
+
+        Ok(TurnScheduler {
+            queue: turns.into_iter().map(Reverse).collect(),
+            time,
+            order,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entity(index: usize) -> GenerationalIndex {
+        GenerationalIndex { index, generation: 1 }
+    }
+
+    #[test]
+    fn delay_scales_with_speed() {
+        assert_eq!(action_delay(MOVE_COST, NORMAL_SPEED), 10);
+        assert_eq!(action_delay(MOVE_COST, NORMAL_SPEED * 2), 5);
+        assert_eq!(action_delay(MOVE_COST, NORMAL_SPEED / 2), 20);
+        assert_eq!(action_delay(WAIT_COST, NORMAL_SPEED), 5);
+        // no speed is very slow, not a divide by zero
+        assert_eq!(action_delay(MOVE_COST, 0), 1000);
+    }
+
+    #[test]
+    fn double_speed_acts_twice_per_turn() {
+        let speeds = [NORMAL_SPEED, NORMAL_SPEED * 2];
+        let mut scheduler = TurnScheduler::new();
+        scheduler.schedule(entity(0), 0);
+        scheduler.schedule(entity(1), 0);
+
+        let mut turns = [0, 0];
+        loop {
+            let next = scheduler.pop().unwrap();
+            if scheduler.time >= 100 {
+                break;
+            }
+            turns[next.index] += 1;
+            scheduler.schedule(next, action_delay(MOVE_COST, speeds[next.index]));
+        }
+
+        assert_eq!(turns, [10, 20]);
+    }
+
+    #[test]
+    fn ties_go_to_whoever_was_scheduled_first() {
+        let mut scheduler = TurnScheduler::new();
+        scheduler.schedule(entity(2), 5);
+        scheduler.schedule(entity(0), 0);
+        scheduler.schedule(entity(1), 0);
+        scheduler.schedule(entity(3), 5);
+
+        let order: Vec<usize> = (0..4).map(|_| scheduler.pop().unwrap().index).collect();
+        assert_eq!(order, vec![0, 1, 2, 3]);
+        assert_eq!(scheduler.time, 5);
+        assert_eq!(scheduler.pop(), None);
+    }
+
+    // a turn scheduled later comes after one already waiting at that time
+    #[test]
+    fn ties_stay_in_order_as_time_moves_on() {
+        let mut scheduler = TurnScheduler::new();
+        scheduler.schedule(entity(0), 10);
+        scheduler.schedule(entity(1), 4);
+
+        assert_eq!(scheduler.pop(), Some(entity(1)));
+        scheduler.schedule(entity(1), 6);
+
+        assert_eq!(scheduler.pop(), Some(entity(0)));
+        assert_eq!(scheduler.pop(), Some(entity(1)));
+        assert_eq!(scheduler.time, 10);
+    }
+
+    #[test]
+    fn removed_entities_get_no_more_turns() {
+        let mut scheduler = TurnScheduler::new();
+        scheduler.schedule(entity(0), 0);
+        scheduler.schedule(entity(1), 5);
+        scheduler.schedule(entity(0), 10);
+        scheduler.remove(entity(0));
+
+        assert!(!scheduler.is_scheduled(entity(0)));
+        assert_eq!(scheduler.len(), 1);
+        assert_eq!(scheduler.pop(), Some(entity(1)));
+    }
+}
@@ -0,0 +1,81 @@
+use std::collections::HashMap;
+
+use crate::renderer::Buffer;
+use crate::world::Entity;
+
+// Which entities are on which cell, built from the collision
+// components each tick alongside the collision buffer
+pub struct SpatialIndex {
+    cells: HashMap<(u16, u16), Vec<Entity>>,
+}
+
+impl SpatialIndex {
+    pub fn new() -> SpatialIndex {
+        SpatialIndex {
+            cells: HashMap::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.clear();
+    }
+
+    pub fn insert(&mut self, position: (u16, u16), entity: Entity) {
+        let entities = self.cells.entry(position).or_default();
+
+        if !entities.contains(&entity) {
+            entities.push(entity);
+        }
+    }
+
+    // every cell of the matrix that is set, like insert_matrix
+    pub fn insert_matrix(&mut self, position: (u16, u16), matrix: &Buffer, entity: Entity) {
+        for (x, col) in matrix.iter().enumerate() {
+            for (y, value) in col.iter().enumerate() {
+                if *value == 1 {
+                    self.insert((position.0 + x as u16, position.1 + y as u16), entity);
+                }
+            }
+        }
+    }
+
+    pub fn remove(&mut self, position: (u16, u16), entity: Entity) {
+        if let Some(entities) = self.cells.get_mut(&position) {
+            entities.retain(|e| *e != entity);
+
+            if entities.is_empty() {
+                self.cells.remove(&position);
+            }
+        }
+    }
+
+    pub fn move_entity(&mut self, from: (u16, u16), to: (u16, u16), entity: Entity) {
+        self.remove(from, entity);
+        self.insert(to, entity);
+    }
+
+    pub fn at(&self, position: (u16, u16)) -> &[Entity] {
+        match self.cells.get(&position) {
+            Some(entities) => entities,
+            None => &[],
+        }
+    }
+
+    // whoever is in the way, ignoring the one asking
+    pub fn blocker(&self, position: (u16, u16), mover: Entity) -> Option<Entity> {
+        self.at(position).iter().find(|e| **e != mover).copied()
+    }
+
+    pub fn is_occupied(&self, position: (u16, u16)) -> bool {
+        !self.at(position).is_empty()
+    }
+}
+
+// Something tried to move onto a cell another entity holds.
+// Movement pushes these, the bump system decides what happens
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CollisionEvent {
+    pub mover: Entity,
+    pub blocker: Entity,
+    pub position: (u16, u16),
+}
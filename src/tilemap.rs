@@ -0,0 +1,213 @@
+use std::io::Result;
+
+use crate::save::{corrupt, Persist, SaveReader, SaveWriter};
+use crate::components::Direction;
+use crate::point::Point;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TileType {
+    Floor,
+    Wall,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MapTile {
+    pub tile_type: TileType,
+    pub glyph: u8, // index into the style map
+    pub remembered_glyph: u8, // seen before but not in view
+    pub walkable: bool,
+    pub opaque: bool,
+    pub move_cost: u32, // for pathfinding, 1 is normal ground
+}
+
+impl MapTile {
+    pub fn new(tile_type: TileType, glyph: u8, remembered_glyph: u8, walkable: bool, opaque: bool) -> MapTile {
+        MapTile {
+            tile_type,
+            glyph,
+            remembered_glyph,
+            walkable,
+            opaque,
+            move_cost: 1,
+        }
+    }
+
+    pub fn floor() -> MapTile {
+        MapTile::new(TileType::Floor, 1, 34, true, false)
+    }
+
+    pub fn wall() -> MapTile {
+        MapTile::new(TileType::Wall, 3, 35, false, true)
+    }
+}
+
+// The level itself, kept out of the ECS so it is
+// stored once and read in place by renderer and collision
+#[derive(Debug, PartialEq)]
+pub struct TileMap {
+    pub dimensions: (usize, usize),
+    tiles: Vec<MapTile>, // column by column, like the buffers
+}
+
+impl TileMap {
+    pub fn new(dimensions: (u16, u16)) -> TileMap {
+        let dimensions = (dimensions.0 as usize, dimensions.1 as usize);
+
+        TileMap {
+            dimensions,
+            tiles: vec![MapTile::floor(); dimensions.0 * dimensions.1],
+        }
+    }
+
+    // 1 is wall, anything else floor, same as CaveMapGenerator
+    pub fn from_generation(generation: &[Vec<u8>]) -> TileMap {
+        let width = generation.len();
+        let height = match generation.first() {
+            Some(col) => col.len(),
+            None => 0,
+        };
+        let mut tilemap = TileMap::new((width as u16, height as u16));
+
+        for (x, col) in generation.iter().enumerate() {
+            for (y, val) in col.iter().enumerate() {
+                if *val == 1 {
+                    tilemap.set((x as u16, y as u16), MapTile::wall());
+                }
+            }
+        }
+
+        tilemap
+    }
+
+    pub fn in_bounds(&self, position: (u16, u16)) -> bool {
+        (position.0 as usize) < self.dimensions.0 && (position.1 as usize) < self.dimensions.1
+    }
+
+    // the neighbouring cell, None if that is off the map
+    pub fn step(&self, position: (u16, u16), direction: Direction) -> Option<(u16, u16)> {
+        Point::from(position).step_within(direction, self.dimensions).map(|point| point.into())
+    }
+
+    pub fn get(&self, position: (u16, u16)) -> Option<&MapTile> {
+        if !self.in_bounds(position) {
+            return None;
+        }
+
+        Some(&self.tiles[self.index(position)])
+    }
+
+    pub fn set(&mut self, position: (u16, u16), tile: MapTile) {
+        if self.in_bounds(position) {
+            let index = self.index(position);
+            self.tiles[index] = tile;
+        }
+    }
+
+    pub fn fill(&mut self, tile: MapTile) {
+        for current in self.tiles.iter_mut() {
+            *current = tile;
+        }
+    }
+
+    // fills the rectangle from tl to br, both included
+    pub fn fill_rect(&mut self, tl: (u16, u16), br: (u16, u16), tile: MapTile) {
+        for x in tl.0..=br.0 {
+            for y in tl.1..=br.1 {
+                self.set((x, y), tile);
+            }
+        }
+    }
+
+    // off the map counts as not walkable
+    pub fn is_walkable(&self, position: (u16, u16)) -> bool {
+        match self.get(position) {
+            Some(tile) => tile.walkable,
+            None => false,
+        }
+    }
+
+    // None if it can't be entered at all
+    pub fn move_cost(&self, position: (u16, u16)) -> Option<u32> {
+        match self.get(position) {
+            Some(tile) if tile.walkable => Some(tile.move_cost),
+            _ => None,
+        }
+    }
+
+    // what an entity standing here is drawn over, whatever is on screen
+    pub fn glyph(&self, position: (u16, u16)) -> Option<u8> {
+        self.get(position).map(|tile| tile.glyph)
+    }
+
+    pub fn is_opaque(&self, position: (u16, u16)) -> bool {
+        match self.get(position) {
+            Some(tile) => tile.opaque,
+            None => true,
+        }
+    }
+
+    fn index(&self, position: (u16, u16)) -> usize {
+        position.0 as usize * self.dimensions.1 + position.1 as usize
+    }
+}
+
+impl Persist for TileType {
+    fn save(&self, out: &mut SaveWriter) {
+        let value: u8 = match self {
+            TileType::Floor => 0,
+            TileType::Wall => 1,
+        };
+        out.write(&value);
+    }
+
+    fn load(input: &mut SaveReader) -> Result<Self> {
+        match input.read::<u8>()? {
+            0 => Ok(TileType::Floor),
+            1 => Ok(TileType::Wall),
+            value => Err(corrupt(&format!("bad tile type {}", value))),
+        }
+    }
+}
+
+impl Persist for MapTile {
+    fn save(&self, out: &mut SaveWriter) {
+        out.write(&self.tile_type);
+        out.write(&self.glyph);
+        out.write(&self.remembered_glyph);
+        out.write(&self.walkable);
+        out.write(&self.opaque);
+        out.write(&self.move_cost);
+    }
+
+    fn load(input: &mut SaveReader) -> Result<Self> {
+        Ok(MapTile {
+            tile_type: input.read()?,
+            glyph: input.read()?,
+            remembered_glyph: input.read()?,
+            walkable: input.read()?,
+            opaque: input.read()?,
+            move_cost: input.read()?,
+        })
+    }
+}
+
+impl Persist for TileMap {
+    fn save(&self, out: &mut SaveWriter) {
+        out.write(&self.dimensions);
+        out.write(&self.tiles);
+    }
+
+    fn load(input: &mut SaveReader) -> Result<Self> {
+        let dimensions: (usize, usize) = input.read()?;
+        let tiles: Vec<MapTile> = input.read()?;
+
+        if tiles.len() != dimensions.0 * dimensions.1 {
+            return Err(corrupt("tile count does not match the map size"));
+        }
+
+        Ok(TileMap {
+            dimensions,
+            tiles,
+        })
+    }
+}
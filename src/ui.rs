@@ -0,0 +1,284 @@
+use crossterm::style::{Color, StyledContent, Stylize};
+
+// An area of the screen, in cells
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Rect {
+    pub fn new(x: u16, y: u16, width: u16, height: u16) -> Rect {
+        Rect { x, y, width, height }
+    }
+
+    // inside a one cell border
+    pub fn inner(&self) -> Rect {
+        Rect::new(self.x + 1, self.y + 1, self.width.saturating_sub(2), self.height.saturating_sub(2))
+    }
+
+    // one row of this, None past the bottom
+    pub fn row(&self, row: u16) -> Option<Rect> {
+        match row < self.height {
+            true => Some(Rect::new(self.x, self.y + row, self.width, 1)),
+            false => None,
+        }
+    }
+}
+
+// Styled cells drawn over the map, screen sized. Anything left as
+// None shows the map underneath. Cleared and drawn again each frame
+#[derive(Clone)]
+pub struct UiLayer {
+    pub width: usize,
+    pub height: usize,
+    cells: Vec<Vec<Option<StyledContent<char>>>>, // [column][row]
+}
+
+impl UiLayer {
+    pub fn new(width: usize, height: usize) -> UiLayer {
+        UiLayer {
+            width,
+            height,
+            cells: vec![vec![None; height]; width],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for col in self.cells.iter_mut() {
+            for cell in col.iter_mut() {
+                *cell = None;
+            }
+        }
+    }
+
+    pub fn get(&self, position: (usize, usize)) -> Option<StyledContent<char>> {
+        match self.cells.get(position.0) {
+            Some(col) => match col.get(position.1) {
+                Some(cell) => *cell,
+                None => None,
+            },
+            None => None,
+        }
+    }
+
+    // off screen is ignored
+    pub fn put(&mut self, position: (u16, u16), content: StyledContent<char>) {
+        if let Some(cell) = self.cells.get_mut(position.0 as usize).and_then(|c| c.get_mut(position.1 as usize)) {
+            *cell = Some(content);
+        }
+    }
+
+    // cut off at the width given
+    pub fn text(&mut self, position: (u16, u16), text: &str, max_width: u16, fg: Color, bg: Color) {
+        for (i, c) in text.chars().take(max_width as usize).enumerate() {
+            self.put((position.0 + i as u16, position.1), c.with(fg).on(bg));
+        }
+    }
+
+    pub fn fill(&mut self, area: Rect, content: StyledContent<char>) {
+        for x in area.x..area.x + area.width {
+            for y in area.y..area.y + area.height {
+                self.put((x, y), content);
+            }
+        }
+    }
+
+    pub fn draw(&mut self, widget: &dyn Widget, area: Rect) {
+        widget.draw(area, self);
+    }
+}
+
+pub trait Widget {
+    fn draw(&self, area: Rect, layer: &mut UiLayer);
+}
+
+// A filled box with a border and an optional title in the top edge
+pub struct Panel {
+    pub title: Option<String>,
+    pub fg: Color,
+    pub bg: Color,
+}
+
+impl Panel {
+    pub fn new(title: Option<&str>) -> Panel {
+        Panel {
+            title: title.map(|t| t.to_string()),
+            fg: Color::White,
+            bg: Color::Black,
+        }
+    }
+}
+
+impl Widget for Panel {
+    fn draw(&self, area: Rect, layer: &mut UiLayer) {
+        if area.width < 2 || area.height < 2 {
+            return;
+        }
+        let right = area.x + area.width - 1;
+        let bottom = area.y + area.height - 1;
+
+        layer.fill(area, ' '.on(self.bg));
+
+        for x in area.x + 1..right {
+            layer.put((x, area.y), '─'.with(self.fg).on(self.bg));
+            layer.put((x, bottom), '─'.with(self.fg).on(self.bg));
+        }
+        for y in area.y + 1..bottom {
+            layer.put((area.x, y), '│'.with(self.fg).on(self.bg));
+            layer.put((right, y), '│'.with(self.fg).on(self.bg));
+        }
+        layer.put((area.x, area.y), '┌'.with(self.fg).on(self.bg));
+        layer.put((right, area.y), '┐'.with(self.fg).on(self.bg));
+        layer.put((area.x, bottom), '└'.with(self.fg).on(self.bg));
+        layer.put((right, bottom), '┘'.with(self.fg).on(self.bg));
+
+        if let Some(title) = &self.title {
+            layer.text((area.x + 2, area.y), title, area.width.saturating_sub(4), self.fg, self.bg);
+        }
+    }
+}
+
+pub struct Label {
+    pub text: String,
+    pub fg: Color,
+    pub bg: Color,
+}
+
+impl Label {
+    pub fn new(text: &str, fg: Color) -> Label {
+        Label {
+            text: text.to_string(),
+            fg,
+            bg: Color::Black,
+        }
+    }
+}
+
+impl Widget for Label {
+    fn draw(&self, area: Rect, layer: &mut UiLayer) {
+        layer.text((area.x, area.y), &self.text, area.width, self.fg, self.bg);
+    }
+}
+
+// eg. health, the label is written over the middle of the bar
+pub struct ProgressBar {
+    pub value: u32,
+    pub max: u32,
+    pub label: Option<String>,
+    pub filled: Color,
+    pub empty: Color,
+}
+
+impl ProgressBar {
+    pub fn new(value: u32, max: u32, filled: Color) -> ProgressBar {
+        ProgressBar {
+            value,
+            max,
+            label: None,
+            filled,
+            empty: Color::DarkGrey,
+        }
+    }
+
+    pub fn with_label(mut self, label: &str) -> ProgressBar {
+        self.label = Some(label.to_string());
+        self
+    }
+}
+
+impl Widget for ProgressBar {
+    fn draw(&self, area: Rect, layer: &mut UiLayer) {
+        let width = area.width as u32;
+        let filled = match self.max {
+            0 => 0,
+            max => (self.value.min(max) * width + max / 2) / max,
+        };
+
+        let label: Vec<char> = match &self.label {
+            Some(label) => label.chars().collect(),
+            None => Vec::new(),
+        };
+        let label_start = (width as usize).saturating_sub(label.len()) / 2;
+
+        for i in 0..area.width {
+            let bg = match (i as u32) < filled {
+                true => self.filled,
+                false => self.empty,
+            };
+            let c = match (i as usize).checked_sub(label_start) {
+                Some(l) if l < label.len() => label[l],
+                _ => ' ',
+            };
+            layer.put((area.x + i, area.y), c.with(Color::White).on(bg));
+        }
+    }
+}
+
+// A list to pick from, drawn as a panel with the choice highlighted
+pub struct Menu {
+    pub title: String,
+    pub items: Vec<String>,
+    pub selected: usize,
+}
+
+impl Menu {
+    pub fn new(title: &str, items: &[&str]) -> Menu {
+        Menu {
+            title: title.to_string(),
+            items: items.iter().map(|i| i.to_string()).collect(),
+            selected: 0,
+        }
+    }
+
+    pub fn select_next(&mut self) {
+        if !self.items.is_empty() {
+            self.selected = (self.selected + 1) % self.items.len();
+        }
+    }
+
+    pub fn select_previous(&mut self) {
+        if !self.items.is_empty() {
+            self.selected = (self.selected + self.items.len() - 1) % self.items.len();
+        }
+    }
+
+    pub fn selected_item(&self) -> Option<&str> {
+        self.items.get(self.selected).map(|i| i.as_str())
+    }
+
+    // smallest box the items fit in
+    pub fn size(&self) -> (u16, u16) {
+        let longest = self.items
+            .iter()
+            .map(|i| i.chars().count())
+            .chain(std::iter::once(self.title.chars().count()))
+            .max()
+            .unwrap_or(0);
+
+        (longest as u16 + 6, self.items.len() as u16 + 2)
+    }
+}
+
+impl Widget for Menu {
+    fn draw(&self, area: Rect, layer: &mut UiLayer) {
+        Panel::new(Some(&self.title)).draw(area, layer);
+
+        let inner = area.inner();
+        for (i, item) in self.items.iter().enumerate() {
+            let row = match inner.row(i as u16) {
+                Some(row) => row,
+                None => break,
+            };
+
+            let (fg, bg) = match i == self.selected {
+                true => (Color::Black, Color::White),
+                false => (Color::White, Color::Black),
+            };
+            layer.fill(row, ' '.on(bg));
+            layer.text((row.x + 1, row.y), item, row.width.saturating_sub(1), fg, bg);
+        }
+    }
+}
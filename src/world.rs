@@ -0,0 +1,306 @@
+use std::any::{Any, TypeId};
+use std::cell::{Ref, RefCell, RefMut};
+use std::collections::HashMap;
+
+use crate::generations::{GenerationalIndex, GenerationalIndexAllocator, GenerationalIndexArray};
+use crate::save::{Persist, SaveReader, SaveWriter};
+
+pub type EntityMap<T> = GenerationalIndexArray<T>;
+pub type Entity = GenerationalIndex;
+
+// Type erased EntityMap, lets the world clear an entity
+// out of every storage without knowing the types
+pub trait Storage: Any {
+    fn remove_entity(&mut self, entity: Entity);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: 'static> Storage for EntityMap<T> {
+    fn remove_entity(&mut self, entity: Entity) {
+        self.remove(entity);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+// All entities and their components, one EntityMap per component type.
+// Storages are RefCells so a system can borrow several at once
+pub struct World {
+    pub entity_allocator: GenerationalIndexAllocator,
+    storages: HashMap<TypeId, RefCell<Box<dyn Storage>>>,
+}
+
+impl World {
+    pub fn new() -> World {
+        World {
+            entity_allocator: GenerationalIndexAllocator::new(1),
+            storages: HashMap::new(),
+        }
+    }
+
+    // does nothing if already registered
+    pub fn register<T: 'static>(&mut self) {
+        let id = TypeId::of::<T>();
+
+        self.storages.entry(id).or_insert_with(|| {
+            let storage: Box<dyn Storage> = Box::new(EntityMap::<T>::new());
+            RefCell::new(storage)
+        });
+    }
+
+    pub fn is_registered<T: 'static>(&self) -> bool {
+        self.storages.contains_key(&TypeId::of::<T>())
+    }
+
+    pub fn spawn(&mut self) -> EntityBuilder<'_> {
+        let entity = self.entity_allocator.allocate();
+
+        EntityBuilder {
+            world: self,
+            entity,
+        }
+    }
+
+    pub fn despawn(&mut self, entity: Entity) -> bool {
+        if !self.entity_allocator.deallocate(entity) {
+            return false;
+        }
+
+        for storage in self.storages.values_mut() {
+            storage.get_mut().remove_entity(entity);
+        }
+
+        true
+    }
+
+    pub fn is_live(&self, entity: Entity) -> bool {
+        self.entity_allocator.is_live(entity)
+    }
+
+    // registers the type if this is the first one
+    pub fn insert<T: 'static>(&mut self, entity: Entity, component: T) {
+        self.register::<T>();
+        self.storage_mut::<T>().set(entity, component);
+    }
+
+    pub fn remove<T: 'static>(&mut self, entity: Entity) -> Option<T> {
+        if !self.is_registered::<T>() {
+            return None;
+        }
+
+        self.storage_mut::<T>().remove(entity)
+    }
+
+    pub fn has<T: 'static>(&self, entity: Entity) -> bool {
+        if !self.is_registered::<T>() {
+            return false;
+        }
+
+        self.borrow::<T>().get(entity).is_some()
+    }
+
+    // Single component, holds a borrow of the whole storage while alive
+    pub fn get<T: 'static>(&self, entity: Entity) -> Option<Ref<'_, T>> {
+        if !self.is_registered::<T>() {
+            return None;
+        }
+
+        Ref::filter_map(self.borrow::<T>(), |storage| storage.get(entity)).ok()
+    }
+
+    pub fn get_mut<T: 'static>(&self, entity: Entity) -> Option<RefMut<'_, T>> {
+        if !self.is_registered::<T>() {
+            return None;
+        }
+
+        RefMut::filter_map(self.borrow_mut::<T>(), |storage| storage.get_mut(entity)).ok()
+    }
+
+    // Whole storage, panics if T was never registered
+    pub fn borrow<T: 'static>(&self) -> Ref<'_, EntityMap<T>> {
+        let cell = match self.storages.get(&TypeId::of::<T>()) {
+            Some(cell) => cell,
+            None => panic!("Component {} not registered!", std::any::type_name::<T>()),
+        };
+
+        Ref::map(cell.borrow(), |storage| {
+            storage.as_any().downcast_ref::<EntityMap<T>>().unwrap()
+        })
+    }
+
+    pub fn borrow_mut<T: 'static>(&self) -> RefMut<'_, EntityMap<T>> {
+        let cell = match self.storages.get(&TypeId::of::<T>()) {
+            Some(cell) => cell,
+            None => panic!("Component {} not registered!", std::any::type_name::<T>()),
+        };
+
+        RefMut::map(cell.borrow_mut(), |storage| {
+            storage.as_any_mut().downcast_mut::<EntityMap<T>>().unwrap()
+        })
+    }
+
+    // One storage in a save. Storages are type erased so whoever
+    // saves lists the component types, and loads them in that order
+    pub fn save_storage<T: Persist + 'static>(&self, out: &mut SaveWriter) {
+        out.section(short_type_name::<T>());
+        out.write(&*self.borrow::<T>());
+    }
+
+    pub fn load_storage<T: Persist + 'static>(&mut self, input: &mut SaveReader) -> std::io::Result<()> {
+        input.section(short_type_name::<T>())?;
+        let storage: EntityMap<T> = input.read()?;
+
+        self.register::<T>();
+        *self.storage_mut::<T>() = storage;
+        Ok(())
+    }
+
+    // no runtime borrow check needed when we have &mut self
+    fn storage_mut<T: 'static>(&mut self) -> &mut EntityMap<T> {
+        match self.storages.get_mut(&TypeId::of::<T>()) {
+            Some(cell) => cell.get_mut().as_any_mut().downcast_mut::<EntityMap<T>>().unwrap(),
+            None => panic!("Component {} not registered!", std::any::type_name::<T>()),
+        }
+    }
+}
+
+pub struct EntityBuilder<'a> {
+    world: &'a mut World,
+    entity: Entity,
+}
+
+impl<'a> EntityBuilder<'a> {
+    pub fn with<T: 'static>(self, component: T) -> EntityBuilder<'a> {
+        self.world.insert(self.entity, component);
+        self
+    }
+
+    pub fn build(self) -> Entity {
+        self.entity
+    }
+}
+
+// module path left off, so moving a type doesn't break saves
+fn short_type_name<T>() -> &'static str {
+    let name = std::any::type_name::<T>();
+
+    match name.rsplit("::").next() {
+        Some(short) => short,
+        None => name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Name(&'static str);
+
+    #[test]
+    fn spawned_components_come_back() {
+        let mut world = World::new();
+        let entity = world.spawn()
+            .with(Name("rat"))
+            .with(7u32)
+            .build();
+
+        assert!(world.is_registered::<Name>());
+        assert!(world.has::<u32>(entity));
+        assert_eq!(*world.get::<Name>(entity).unwrap(), Name("rat"));
+
+        *world.get_mut::<u32>(entity).unwrap() += 1;
+        assert_eq!(*world.get::<u32>(entity).unwrap(), 8);
+
+        assert_eq!(world.remove::<u32>(entity), Some(8));
+        assert!(!world.has::<u32>(entity));
+        assert!(world.has::<Name>(entity));
+    }
+
+    // never registered is just nothing there, not a panic
+    #[test]
+    fn unregistered_components_are_none() {
+        let mut world = World::new();
+        let entity = world.spawn().build();
+
+        assert!(!world.has::<Name>(entity));
+        assert!(world.get::<Name>(entity).is_none());
+        assert_eq!(world.remove::<Name>(entity), None);
+    }
+
+    #[test]
+    fn despawn_clears_every_storage() {
+        let mut world = World::new();
+        let entity = world.spawn().with(Name("rat")).with(7u32).build();
+
+        assert!(world.despawn(entity));
+        assert!(!world.is_live(entity));
+        assert!(world.get::<Name>(entity).is_none());
+        assert_eq!(world.borrow::<u32>().iter().count(), 0);
+        // only once
+        assert!(!world.despawn(entity));
+    }
+
+    // the slot gets reused, the old handle must not see the new entity
+    #[test]
+    fn stale_handles_find_nothing() {
+        let mut world = World::new();
+        let old = world.spawn().with(Name("rat")).build();
+        world.despawn(old);
+        let new = world.spawn().with(Name("bat")).build();
+
+        assert_eq!(old.index(), new.index());
+        assert!(!world.is_live(old));
+        assert!(world.get::<Name>(old).is_none());
+        assert!(world.get_mut::<Name>(old).is_none());
+        assert!(!world.despawn(old));
+        assert_eq!(*world.get::<Name>(new).unwrap(), Name("bat"));
+    }
+
+    #[test]
+    fn storages_save_and_load() {
+        let mut world = World::new();
+        let a = world.spawn().with(3u32).with(true).build();
+        let b = world.spawn().with(5u32).build();
+        world.despawn(a);
+        let c = world.spawn().with(9u32).build();
+
+        let mut out = SaveWriter::new();
+        out.write(&world.entity_allocator);
+        world.save_storage::<u32>(&mut out);
+        world.save_storage::<bool>(&mut out);
+
+        let mut input = SaveReader::new(&out.finish()).unwrap();
+        let mut loaded = World::new();
+        loaded.entity_allocator = input.read().unwrap();
+        loaded.load_storage::<u32>(&mut input).unwrap();
+        loaded.load_storage::<bool>(&mut input).unwrap();
+
+        assert_eq!(loaded.entity_allocator, world.entity_allocator);
+        assert_eq!(*loaded.borrow::<u32>(), *world.borrow::<u32>());
+        assert_eq!(*loaded.get::<u32>(b).unwrap(), 5);
+        assert_eq!(*loaded.get::<u32>(c).unwrap(), 9);
+        assert!(loaded.get::<u32>(a).is_none());
+        assert!(loaded.get::<bool>(a).is_none());
+    }
+
+    // the section names the storage, loading the wrong one is an error
+    #[test]
+    fn storages_load_in_the_saved_order() {
+        let mut world = World::new();
+        world.spawn().with(3u32).build();
+
+        let mut out = SaveWriter::new();
+        world.save_storage::<u32>(&mut out);
+
+        let mut input = SaveReader::new(&out.finish()).unwrap();
+        assert!(World::new().load_storage::<bool>(&mut input).is_err());
+    }
+}